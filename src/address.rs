@@ -0,0 +1,147 @@
+use regex::bytes::Regex;
+use std::ops::Range;
+
+/// The lines a replacement is restricted to, like sed addresses.
+pub enum Address {
+    /// From the line `first` to the line `last`, counting from 1.
+    Lines { first: usize, last: usize },
+    /// From each line matching `from` to the next one matching `to`, or to
+    /// the end of the text.
+    Patterns { from: Regex, to: Option<Regex> },
+}
+
+/// Parses a `N:M` range of lines, either bound being optional.
+pub fn parse_lines(s: &str) -> Result<(usize, usize), String> {
+    let (first, last) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected FIRST:LAST, got {:?}", s))?;
+    let bound = |b: &str, default| match b {
+        "" => Ok(default),
+        b => b
+            .parse::<usize>()
+            .ok()
+            .filter(|&n| n > 0)
+            .ok_or_else(|| format!("invalid line number {:?}", b)),
+    };
+    let (first, last) = (bound(first, 1)?, bound(last, usize::MAX)?);
+    if first > last {
+        return Err(format!("the range {:?} is empty", s));
+    }
+    Ok((first, last))
+}
+
+/// Follows which lines of a text are addressed, the text being given in
+/// consecutive parts made of whole lines.
+pub struct Region<'a> {
+    address: &'a Address,
+    /// Number of the last line seen.
+    line: usize,
+    /// Whether the last line seen was between a `from` and a `to` match.
+    inside: bool,
+}
+
+impl<'a> Region<'a> {
+    pub fn new(address: &'a Address) -> Self {
+        Region {
+            address,
+            line: 0,
+            inside: false,
+        }
+    }
+
+    /// Returns whether the next line, given without its line ending, is
+    /// addressed.
+    fn next(&mut self, line: &[u8]) -> bool {
+        self.line += 1;
+        match self.address {
+            Address::Lines { first, last } => (*first..=*last).contains(&self.line),
+            Address::Patterns { from, to } => {
+                if self.inside {
+                    // The end is looked for after the line which started the
+                    // region, so both can match the same pattern.
+                    self.inside = !to.as_ref().is_some_and(|to| to.is_match(line));
+                    true
+                } else {
+                    self.inside = from.is_match(line);
+                    self.inside
+                }
+            }
+        }
+    }
+
+    /// Returns the spans of the addressed lines of `cnt`, which follows the
+    /// parts given so far.
+    pub fn ranges(&mut self, cnt: &[u8]) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = Vec::new();
+        let mut start = 0;
+        for line in cnt.split_inclusive(|&b| b == b'\n') {
+            let end = start + line.len();
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if self.next(line) {
+                match ranges.last_mut() {
+                    Some(last) if last.end == start => last.end = end,
+                    _ => ranges.push(start..end),
+                }
+            }
+            start = end;
+        }
+        ranges
+    }
+}
+
+/// Returns whether `range` lies within one of the sorted `ranges`.
+pub fn contains(ranges: &[Range<usize>], range: Range<usize>) -> bool {
+    let i = ranges.partition_point(|r| r.end < range.end);
+    ranges
+        .get(i)
+        .is_some_and(|r| r.start <= range.start && range.end <= r.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line_ranges() {
+        assert_eq!(parse_lines("2:5"), Ok((2, 5)));
+        assert_eq!(parse_lines(":5"), Ok((1, 5)));
+        assert_eq!(parse_lines("3:"), Ok((3, usize::MAX)));
+        assert_eq!(parse_lines("4:4"), Ok((4, 4)));
+        for s in ["5", "0:3", "a:3", "5:2", "-1:3"] {
+            assert!(parse_lines(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn line_region() {
+        let address = Address::Lines { first: 2, last: 3 };
+        let mut region = Region::new(&address);
+        assert_eq!(region.ranges(b"a\nb\n"), vec![2..4]);
+        // Continued with the next part, the offsets being relative to it.
+        assert_eq!(region.ranges(b"c\nd\n"), vec![0..2]);
+    }
+
+    #[test]
+    fn pattern_region() {
+        let address = Address::Patterns {
+            from: Regex::new("^begin").unwrap(),
+            to: Some(Regex::new("end$").unwrap()),
+        };
+        let text = b"x\nbegin end\ny\nend\r\nz\nbegin\n";
+        // The line starting a section can't end it, and a section left open
+        // goes on to the end.
+        assert_eq!(Region::new(&address).ranges(text), vec![2..19, 21..27]);
+    }
+
+    #[test]
+    fn contains_ranges() {
+        let ranges = [2..5, 8..10];
+        assert!(contains(&ranges, 2..5));
+        assert!(contains(&ranges, 3..4));
+        assert!(contains(&ranges, 8..8));
+        assert!(!contains(&ranges, 4..6));
+        assert!(!contains(&ranges, 0..1));
+        assert!(!contains(&ranges, 10..11));
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary file created next to `target` which replaces it on commit.
+///
+/// Readers of `target` only ever see either the old or the new content: if
+/// anything fails before `commit` the temporary file is removed on drop and
+/// the original is left untouched.
+pub struct AtomicFile {
+    file: File,
+    tmp: PathBuf,
+    target: PathBuf,
+    metadata: fs::Metadata,
+    committed: bool,
+}
+
+impl AtomicFile {
+    /// Creates the temporary file for `target`, which must already exist.
+    /// Symlinks are resolved so the file they point to is the one replaced.
+    pub fn create(target: &Path) -> io::Result<Self> {
+        let target = fs::canonicalize(target)?;
+        let metadata = fs::metadata(&target)?;
+        let dir = target.parent().unwrap_or(Path::new("."));
+        let name = target.file_name().unwrap_or_default().to_string_lossy();
+
+        loop {
+            let tmp = dir.join(format!(
+                ".{}.jet{}-{}.tmp",
+                name,
+                process::id(),
+                TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            match OpenOptions::new().write(true).create_new(true).open(&tmp) {
+                Ok(file) => {
+                    return Ok(AtomicFile {
+                        file,
+                        tmp,
+                        target,
+                        metadata,
+                        committed: false,
+                    })
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Flushes the new content to disk and moves it over the target, giving
+    /// it the target's permissions and, where allowed, its ownership.
+    /// With `preserve_mtime` the target's modification time is kept as well.
+    pub fn commit(mut self, preserve_mtime: bool) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{fchown, MetadataExt};
+            // Only root may give a file away, everyone else can at most
+            // keep the group, so failing here is not an error.
+            if fchown(
+                &self.file,
+                Some(self.metadata.uid()),
+                Some(self.metadata.gid()),
+            )
+            .is_err()
+            {
+                let _ = fchown(&self.file, None, Some(self.metadata.gid()));
+            }
+        }
+        // After the ownership, as changing it clears the setuid and setgid bits.
+        self.file.set_permissions(self.metadata.permissions())?;
+        if preserve_mtime {
+            self.file.set_modified(self.metadata.modified()?)?;
+        }
+        self.file.sync_all()?;
+        fs::rename(&self.tmp, &self.target)?;
+        self.committed = true;
+
+        #[cfg(unix)]
+        if let Some(dir) = self.target.parent() {
+            // Make the rename itself durable.
+            let _ = File::open(dir).and_then(|d| d.sync_all());
+        }
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Atomically replaces the content of the file at `path` with `content`.
+pub fn write(path: &Path, content: &[u8], preserve_mtime: bool) -> io::Result<()> {
+    let mut file = AtomicFile::create(path)?;
+    file.write_all(content)?;
+    file.commit(preserve_mtime)
+}
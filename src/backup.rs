@@ -0,0 +1,68 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Where the original of an edited file is kept.
+#[derive(Debug)]
+pub struct Backup {
+    /// Appended to the file name of the backup.
+    pub suffix: Option<String>,
+    /// Root of a tree mirroring the absolute paths of the edited files.
+    pub dir: Option<PathBuf>,
+}
+
+impl Backup {
+    pub fn new(suffix: Option<String>, dir: Option<PathBuf>) -> Option<Self> {
+        if suffix.is_none() && dir.is_none() {
+            return None;
+        }
+        Some(Backup { suffix, dir })
+    }
+
+    /// Returns the path the backup of `path` is stored at.
+    pub fn path_for(&self, path: &Path) -> io::Result<PathBuf> {
+        let mut dest = match &self.dir {
+            Some(dir) => {
+                let abs = fs::canonicalize(path)?;
+                let rel: PathBuf = abs
+                    .components()
+                    .filter(|c| matches!(c, Component::Normal(_)))
+                    .collect();
+                dir.join(rel)
+            }
+            None => path.to_path_buf(),
+        };
+        if let Some(suffix) = &self.suffix {
+            let mut name = OsString::from(dest.file_name().unwrap_or_default());
+            name.push(suffix);
+            dest.set_file_name(name);
+        }
+        Ok(dest)
+    }
+
+    /// Saves the current content of `path`, replacing any previous backup.
+    pub fn save(&self, path: &Path) -> io::Result<PathBuf> {
+        let src = fs::canonicalize(path)?;
+        let dest = self.path_for(path)?;
+        if fs::canonicalize(&dest).is_ok_and(|d| d == src) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "backup would overwrite the original",
+            ));
+        }
+        if let Some(dir) = dest.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        match fs::remove_file(&dest) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        // Edited files are replaced by a rename rather than overwritten, so
+        // a hard link keeps the original content just as well as a copy.
+        if fs::hard_link(&src, &dest).is_err() {
+            fs::copy(&src, &dest)?;
+        }
+        Ok(dest)
+    }
+}
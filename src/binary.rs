@@ -0,0 +1,31 @@
+/// How much of a file is looked at to tell whether it is binary.
+const SNIFF_LEN: usize = 8192;
+
+/// Signatures of common binary formats which may not have a NUL byte early on.
+const MAGIC: [&[u8]; 14] = [
+    b"\x7fELF",
+    b"\xca\xfe\xba\xbe",
+    b"\xcf\xfa\xed\xfe",
+    b"\xce\xfa\xed\xfe",
+    b"\x89PNG\r\n\x1a\n",
+    b"GIF87a",
+    b"GIF89a",
+    b"\xff\xd8\xff",
+    b"PK\x03\x04",
+    b"\x1f\x8b",
+    b"BZh",
+    b"7z\xbc\xaf\x27\x1c",
+    b"%PDF-",
+    b"\x28\xb5\x2f\xfd",
+];
+
+/// Returns whether `cnt` starts with the signature of a binary format.
+pub fn has_magic(cnt: &[u8]) -> bool {
+    MAGIC.iter().any(|magic| cnt.starts_with(magic))
+}
+
+/// Returns whether `cnt` looks like the content of a binary file: it starts
+/// with a known signature or has a NUL byte in its first block.
+pub fn is_binary(cnt: &[u8]) -> bool {
+    has_magic(cnt) || cnt[..cnt.len().min(SNIFF_LEN)].contains(&0)
+}
@@ -0,0 +1,194 @@
+use std::mem;
+
+/// Returns whether `c` separates the words of an identifier.
+fn is_separator(c: char) -> bool {
+    c == '_' || c == '-'
+}
+
+/// Splits `ident` into its words, at `_` and `-` and where the case changes
+/// like in `fooBar` or `HTTPServer`.
+fn words(ident: &str) -> Vec<String> {
+    let chars: Vec<char> = ident.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if is_separator(c) {
+            if !word.is_empty() {
+                words.push(mem::take(&mut word));
+            }
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        let boundary = c.is_uppercase()
+            && prev.is_some_and(|p| {
+                p.is_lowercase()
+                    || p.is_numeric()
+                    || (p.is_uppercase() && next.is_some_and(|n| n.is_lowercase()))
+            });
+        if boundary && !word.is_empty() {
+            words.push(mem::take(&mut word));
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Returns `word` with its first letter in uppercase and the others in
+/// lowercase.
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or(String::new(), |first| {
+        first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect()
+    })
+}
+
+/// An identifier split into its words, to be written in the styles of the
+/// others.
+pub struct Identifier {
+    source: String,
+    words: Vec<String>,
+    separator: Option<char>,
+}
+
+impl Identifier {
+    pub fn new(ident: &str) -> Self {
+        Identifier {
+            source: ident.to_string(),
+            words: words(ident),
+            separator: ident.chars().find(|&c| is_separator(c)),
+        }
+    }
+
+    /// Returns the identifier as given and in snake_case, SCREAMING_SNAKE_CASE,
+    /// camelCase and PascalCase, plus kebab-case if given that way, longest
+    /// first.
+    pub fn variants(&self) -> Vec<String> {
+        let lower: Vec<String> = self.words.iter().map(|w| w.to_lowercase()).collect();
+        let capitalized: Vec<String> = self.words.iter().map(|w| capitalize(w)).collect();
+        let mut variants = vec![
+            self.source.clone(),
+            lower.join("_"),
+            lower.join("_").to_uppercase(),
+            capitalized.concat(),
+        ];
+        if let Some(first) = lower.first() {
+            variants.push(format!("{}{}", first, capitalized[1..].concat()));
+        }
+        if self.separator == Some('-') {
+            variants.push(lower.join("-"));
+            variants.push(lower.join("-").to_uppercase());
+        }
+        variants.retain(|v| !v.is_empty());
+        variants.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        variants.dedup();
+        variants
+    }
+
+    /// Returns the identifier written in the style of `other`.
+    pub fn restyle(&self, other: &str) -> String {
+        let upper = other.chars().any(char::is_uppercase);
+        let lower = other.chars().any(char::is_lowercase);
+        // A single word in a single case, like `foo` or `FOO`, tells nothing
+        // about how to join the words, so they are joined as given, or like
+        // in SCREAMING_SNAKE_CASE in uppercase.
+        let separator = match other.chars().find(|&c| is_separator(c)) {
+            Some(c) => Some(c),
+            None if upper && lower => None,
+            None if self.separator.is_some() => self.separator,
+            None if lower => return self.source.clone(),
+            None => Some('_'),
+        };
+        let capitalized = other
+            .chars()
+            .find(|c| c.is_alphabetic())
+            .is_some_and(char::is_uppercase);
+        let mut out = String::new();
+        for (i, word) in self.words.iter().enumerate() {
+            if i > 0 {
+                out.extend(separator);
+            }
+            let word = if !lower {
+                word.to_uppercase()
+            } else if !upper || (i == 0 && !capitalized) {
+                word.to_lowercase()
+            } else {
+                capitalize(word)
+            };
+            out.push_str(&word);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_words() {
+        let cases: [(&str, &[&str]); 9] = [
+            ("foo", &["foo"]),
+            ("foo_bar", &["foo", "bar"]),
+            ("FOO_BAR", &["FOO", "BAR"]),
+            ("foo-bar", &["foo", "bar"]),
+            ("fooBar", &["foo", "Bar"]),
+            ("FooBar", &["Foo", "Bar"]),
+            ("HTTPServer", &["HTTP", "Server"]),
+            ("vec2Builder", &["vec2", "Builder"]),
+            ("__foo__bar_", &["foo", "bar"]),
+        ];
+        for (ident, expected) in cases {
+            assert_eq!(words(ident), expected, "{:?}", ident);
+        }
+    }
+
+    #[test]
+    fn variants() {
+        assert_eq!(
+            Identifier::new("foo_bar").variants(),
+            ["FOO_BAR", "foo_bar", "FooBar", "fooBar"]
+        );
+        assert_eq!(
+            Identifier::new("foo-bar").variants(),
+            ["FOO-BAR", "FOO_BAR", "foo-bar", "foo_bar", "FooBar", "fooBar"]
+        );
+        assert_eq!(Identifier::new("foo").variants(), ["FOO", "Foo", "foo"]);
+        assert!(Identifier::new("_").variants().iter().all(|v| v == "_"));
+    }
+
+    #[test]
+    fn restyle() {
+        let cases = [
+            ("baz_qux", "foo_bar", "baz_qux"),
+            ("baz_qux", "FooBar", "BazQux"),
+            ("baz_qux", "FOO_BAR", "BAZ_QUX"),
+            ("baz_qux", "fooBar", "bazQux"),
+            ("baz_qux", "foo-bar", "baz-qux"),
+            ("baz_qux", "foo", "baz_qux"),
+            ("baz_qux", "FOO", "BAZ_QUX"),
+            ("baz_qux", "Foo", "BazQux"),
+            ("barBaz", "foo", "barBaz"),
+            ("barBaz", "Foo", "BarBaz"),
+            ("barBaz", "FOO", "BAR_BAZ"),
+            ("HTTPServer", "foo_bar", "http_server"),
+            ("HTTPServer", "FooBar", "HttpServer"),
+            ("qux", "FOO_BAR", "QUX"),
+        ];
+        for (replacement, matched, expected) in cases {
+            assert_eq!(
+                Identifier::new(replacement).restyle(matched),
+                expected,
+                "{:?} like {:?}",
+                replacement,
+                matched
+            );
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use crate::binary;
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Returns the edit script turning `a` into `b`, as computed by Myers' algorithm.
+fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Op> {
+    // Common prefix and suffix never take part in the edit script, trimming
+    // them keeps the search space small for the usual few-lines-changed case.
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let mut ops = vec![Op::Equal; prefix];
+    ops.extend(myers(
+        &a[prefix..a.len() - suffix],
+        &b[prefix..b.len() - suffix],
+    ));
+    ops.extend(std::iter::repeat_n(Op::Equal, suffix));
+    ops
+}
+
+fn myers<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Op> {
+    if a.is_empty() || b.is_empty() {
+        let mut ops = vec![Op::Delete; a.len()];
+        ops.extend(std::iter::repeat_n(Op::Insert, b.len()));
+        return ops;
+    }
+
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let at = |k: isize| (k + max) as usize;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    // For every d we only keep the diagonals that were reachable, which
+    // bounds the memory to O(D²) instead of O(D·(N+M)).
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) {
+                v[at(k + 1)]
+            } else {
+                v[at(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+        trace.push(v[at(-d)..=at(d)].to_vec());
+    }
+
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (1..=trace.len() as isize).rev() {
+        let prev = &trace[(d - 1) as usize];
+        let get = |k: isize| prev[(k + d - 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = get(prev_k);
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        ops.push(if x == prev_x { Op::Insert } else { Op::Delete });
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.extend(std::iter::repeat_n(Op::Equal, x as usize));
+    ops.reverse();
+    ops
+}
+
+fn push_line(out: &mut Vec<u8>, sign: u8, line: &[u8]) {
+    out.push(sign);
+    out.extend_from_slice(line);
+    if !line.ends_with(b"\n") {
+        out.extend_from_slice(b"\n\\ No newline at end of file\n");
+    }
+}
+
+/// Returns the unified diff between `old` and `new` with `context` lines
+/// around each change, or nothing if they are identical. Binary contents
+/// only get a line saying they differ, like git does.
+pub fn unified(old: &[u8], new: &[u8], old_name: &str, new_name: &str, context: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    if old == new {
+        return out;
+    }
+    if binary::is_binary(old) || binary::is_binary(new) {
+        let _ = writeln!(out, "Binary files {} and {} differ", old_name, new_name);
+        return out;
+    }
+    let a: Vec<&[u8]> = old.split_inclusive(|&b| b == b'\n').collect();
+    let b: Vec<&[u8]> = new.split_inclusive(|&b| b == b'\n').collect();
+    let ops = diff(&a, &b);
+
+    // Line offsets in `a` and `b` before each op.
+    let mut pos = Vec::with_capacity(ops.len() + 1);
+    let (mut i, mut j) = (0, 0);
+    for op in &ops {
+        pos.push((i, j));
+        match op {
+            Op::Equal => {
+                i += 1;
+                j += 1;
+            }
+            Op::Delete => i += 1,
+            Op::Insert => j += 1,
+        }
+    }
+    pos.push((i, j));
+
+    let mut next = 0;
+    while let Some(first) = ops[next..].iter().position(|&op| op != Op::Equal) {
+        let start = next + first;
+        let mut end = start;
+        // Extend the hunk while the gap to the next change is small enough
+        // for their contexts to touch.
+        loop {
+            while end < ops.len() && ops[end] != Op::Equal {
+                end += 1;
+            }
+            match ops[end..].iter().position(|&op| op != Op::Equal) {
+                Some(gap) if gap <= 2 * context => end += gap,
+                _ => break,
+            }
+        }
+
+        let lo = start.saturating_sub(context);
+        let hi = (end + context).min(ops.len());
+        let (a_lo, b_lo) = pos[lo];
+        let (a_hi, b_hi) = pos[hi];
+        let (a_len, b_len) = (a_hi - a_lo, b_hi - b_lo);
+
+        if out.is_empty() {
+            let _ = writeln!(out, "--- {}\n+++ {}", old_name, new_name);
+        }
+        let _ = writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            if a_len == 0 { a_lo } else { a_lo + 1 },
+            a_len,
+            if b_len == 0 { b_lo } else { b_lo + 1 },
+            b_len,
+        );
+        for (op, &(i, j)) in ops[lo..hi].iter().zip(&pos[lo..hi]) {
+            match op {
+                Op::Equal => push_line(&mut out, b' ', a[i]),
+                Op::Delete => push_line(&mut out, b'-', a[i]),
+                Op::Insert => push_line(&mut out, b'+', b[j]),
+            }
+        }
+        next = hi;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies `ops` to `a`, taking the inserted items from `b`.
+    fn apply(ops: &[Op], a: &[char], b: &[char]) -> Vec<char> {
+        let (mut i, mut j) = (0, 0);
+        let mut out = Vec::new();
+        for op in ops {
+            match op {
+                Op::Equal => {
+                    assert_eq!(a[i], b[j]);
+                    out.push(a[i]);
+                    i += 1;
+                    j += 1;
+                }
+                Op::Delete => i += 1,
+                Op::Insert => {
+                    out.push(b[j]);
+                    j += 1;
+                }
+            }
+        }
+        assert_eq!((i, j), (a.len(), b.len()));
+        out
+    }
+
+    #[test]
+    fn diff_is_minimal() {
+        let cases: &[(&str, &str, usize)] = &[
+            ("", "", 0),
+            ("abc", "abc", 0),
+            ("", "abc", 3),
+            ("abc", "", 3),
+            ("abcabba", "cbabac", 5),
+            ("abcdef", "abxdef", 2),
+            ("xabc", "abcx", 2),
+        ];
+        for &(a, b, edits) in cases {
+            let a: Vec<char> = a.chars().collect();
+            let b: Vec<char> = b.chars().collect();
+            let ops = diff(&a, &b);
+            assert_eq!(apply(&ops, &a, &b), b, "{:?} -> {:?}", a, b);
+            let count = ops.iter().filter(|&&op| op != Op::Equal).count();
+            assert_eq!(count, edits, "{:?} -> {:?}", a, b);
+        }
+    }
+
+    fn unified_str(old: &str, new: &str, context: usize) -> String {
+        let out = unified(old.as_bytes(), new.as_bytes(), "a/f", "b/f", context);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn unified_identical() {
+        assert_eq!(unified_str("a\nb\n", "a\nb\n", 3), "");
+    }
+
+    #[test]
+    fn unified_single_change() {
+        assert_eq!(
+            unified_str("1\n2\n3\n4\n5\n", "1\n2\nx\n4\n5\n", 1),
+            "--- a/f\n+++ b/f\n@@ -2,3 +2,3 @@\n 2\n-3\n+x\n 4\n",
+        );
+    }
+
+    #[test]
+    fn unified_insertion_into_empty() {
+        assert_eq!(
+            unified_str("", "a\n", 3),
+            "--- a/f\n+++ b/f\n@@ -0,0 +1,1 @@\n+a\n",
+        );
+    }
+
+    #[test]
+    fn unified_no_newline_at_end() {
+        assert_eq!(
+            unified_str("a\nb", "a\nc", 0),
+            "--- a/f\n+++ b/f\n@@ -2,1 +2,1 @@\n-b\n\\ No newline at end of file\n\
+             +c\n\\ No newline at end of file\n",
+        );
+    }
+
+    #[test]
+    fn unified_merges_close_hunks() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        // The contexts of changes two lines apart touch.
+        assert_eq!(
+            unified_str(old, "1\nx\n3\n4\ny\n6\n7\n8\n9\n", 1),
+            "--- a/f\n+++ b/f\n@@ -1,6 +1,6 @@\n 1\n-2\n+x\n 3\n 4\n-5\n+y\n 6\n",
+        );
+        // Those of changes further apart don't.
+        assert_eq!(
+            unified_str(old, "1\nx\n3\n4\n5\n6\n7\ny\n9\n", 1),
+            "--- a/f\n+++ b/f\n@@ -1,3 +1,3 @@\n 1\n-2\n+x\n 3\n\
+             @@ -7,3 +7,3 @@\n 7\n-8\n+y\n 9\n",
+        );
+    }
+
+    #[test]
+    fn unified_binary() {
+        let out = unified(b"a\0b", b"a\0c", "a/f", "b/f", 3);
+        assert_eq!(out, b"Binary files a/f and b/f differ\n");
+    }
+}
@@ -0,0 +1,302 @@
+use crate::jis0208::JIS0208;
+use clap::ValueEnum;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// The encodings files can be read and written back in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    #[value(name = "utf-8", alias = "utf8")]
+    Utf8,
+    #[value(name = "utf-16le", alias = "utf16le")]
+    Utf16Le,
+    #[value(name = "utf-16be", alias = "utf16be")]
+    Utf16Be,
+    #[value(name = "latin1", alias = "iso-8859-1")]
+    Latin1,
+    #[value(name = "windows-1252", alias = "cp1252")]
+    Windows1252,
+    #[value(name = "shift-jis", aliases = ["shift_jis", "sjis"])]
+    ShiftJis,
+}
+
+/// The characters of Windows-1252 from 0x80 to 0x9f, the undefined ones
+/// standing for the C1 control with the same code like browsers do.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+            Encoding::Windows1252 => "Windows-1252",
+            Encoding::ShiftJis => "Shift-JIS",
+        })
+    }
+}
+
+impl Encoding {
+    pub fn is_utf16(self) -> bool {
+        matches!(self, Encoding::Utf16Le | Encoding::Utf16Be)
+    }
+
+    fn bom(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => b"\xef\xbb\xbf",
+            Encoding::Utf16Le => b"\xff\xfe",
+            Encoding::Utf16Be => b"\xfe\xff",
+            Encoding::Latin1 | Encoding::Windows1252 | Encoding::ShiftJis => b"",
+        }
+    }
+}
+
+/// Returns the character of the Shift-JIS double byte code `lead`, `trail`.
+fn shift_jis_char(lead: u8, trail: u8) -> Option<char> {
+    let base = match lead {
+        0x81..=0x9f => lead - 0x81,
+        0xe0..=0xef => lead - 0xc1,
+        _ => return None,
+    } as usize;
+    // Each lead byte covers two rows of JIS X 0208.
+    let (row, cell) = match trail {
+        0x40..=0x7e => (base * 2, trail - 0x40),
+        0x80..=0x9e => (base * 2, trail - 0x41),
+        0x9f..=0xfc => (base * 2 + 1, trail - 0x9f),
+        _ => return None,
+    };
+    match JIS0208[row * 94 + cell as usize] {
+        0 => None,
+        c => char::from_u32(c.into()),
+    }
+}
+
+/// Returns the Shift-JIS double byte code of `c`, if JIS X 0208 has it.
+fn shift_jis_code(c: char) -> Option<[u8; 2]> {
+    static CODES: OnceLock<HashMap<u16, usize>> = OnceLock::new();
+    let codes = CODES.get_or_init(|| {
+        (JIS0208.iter().enumerate())
+            .filter(|(_, &c)| c != 0)
+            .map(|(i, &c)| (c, i))
+            .collect()
+    });
+    let i = *codes.get(&u16::try_from(u32::from(c)).ok()?)?;
+    let (row, cell) = (i / 94, (i % 94) as u8);
+    let lead = (row / 2) as u8 + if row < 62 { 0x81 } else { 0xc1 };
+    let trail = match (row % 2, cell) {
+        (0, 0..=0x3e) => cell + 0x40,
+        (0, _) => cell + 0x41,
+        _ => cell + 0x9f,
+    };
+    Some([lead, trail])
+}
+
+/// How the content of a file is stored, to write it back the same way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Format {
+    pub encoding: Encoding,
+    /// Whether the content starts with a byte order mark.
+    pub bom: bool,
+}
+
+impl Format {
+    /// Returns the format of `raw` given by its byte order mark if any, or
+    /// `encoding` without one.
+    pub fn detect(raw: &[u8], encoding: Encoding) -> Self {
+        [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be]
+            .into_iter()
+            .find(|e| raw.starts_with(e.bom()))
+            .map(|encoding| Format {
+                encoding,
+                bom: true,
+            })
+            .unwrap_or(Format {
+                encoding,
+                bom: false,
+            })
+    }
+
+    /// Returns `raw` as UTF-8 without its byte order mark, or `None` if it is
+    /// not valid in this format. UTF-8 content is only checked by the caller,
+    /// as byte mode lifts the requirement.
+    pub fn decode(self, mut raw: Vec<u8>) -> Option<Vec<u8>> {
+        if self.bom {
+            raw.drain(..self.encoding.bom().len());
+        }
+        let (chunks, rest) = (raw.chunks_exact(2), raw.len() % 2);
+        let text: String = match self.encoding {
+            Encoding::Utf8 => return Some(raw),
+            Encoding::Utf16Le if rest == 0 => {
+                let units = chunks.map(|u| u16::from_le_bytes([u[0], u[1]]));
+                char::decode_utf16(units).collect::<Result<_, _>>().ok()?
+            }
+            Encoding::Utf16Be if rest == 0 => {
+                let units = chunks.map(|u| u16::from_be_bytes([u[0], u[1]]));
+                char::decode_utf16(units).collect::<Result<_, _>>().ok()?
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => return None,
+            Encoding::Latin1 => raw.iter().map(|&b| b as char).collect(),
+            Encoding::Windows1252 => raw
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9f => WINDOWS_1252[b as usize - 0x80],
+                    b => b as char,
+                })
+                .collect(),
+            Encoding::ShiftJis => {
+                let mut text = String::with_capacity(raw.len());
+                let mut bytes = raw.iter();
+                while let Some(&b) = bytes.next() {
+                    text.push(match b {
+                        0x00..=0x7f => b as char,
+                        // Half-width katakana.
+                        0xa1..=0xdf => char::from_u32(0xff61 + (b - 0xa1) as u32)?,
+                        _ => shift_jis_char(b, *bytes.next()?)?,
+                    });
+                }
+                text
+            }
+        };
+        Some(text.into_bytes())
+    }
+
+    /// Returns `text`, which must be UTF-8 unless the format is, in this
+    /// format, or the first character it can't represent.
+    pub fn encode(self, text: &[u8]) -> Result<Cow<'_, [u8]>, char> {
+        if self.encoding == Encoding::Utf8 && !self.bom {
+            return Ok(Cow::Borrowed(text));
+        }
+        let mut out = Vec::with_capacity(text.len() + 3);
+        if self.bom {
+            out.extend_from_slice(self.encoding.bom());
+        }
+        let chars = String::from_utf8_lossy(text);
+        match self.encoding {
+            Encoding::Utf8 => out.extend_from_slice(text),
+            Encoding::Utf16Le => chars
+                .encode_utf16()
+                .for_each(|u| out.extend_from_slice(&u.to_le_bytes())),
+            Encoding::Utf16Be => chars
+                .encode_utf16()
+                .for_each(|u| out.extend_from_slice(&u.to_be_bytes())),
+            Encoding::Latin1 => {
+                for c in chars.chars() {
+                    out.push(u8::try_from(c).map_err(|_| c)?);
+                }
+            }
+            Encoding::Windows1252 => {
+                for c in chars.chars() {
+                    let b = match u8::try_from(c) {
+                        Ok(b) if !(0x80..=0x9f).contains(&b) => b,
+                        _ => match WINDOWS_1252.iter().position(|&w| w == c) {
+                            Some(i) => 0x80 + i as u8,
+                            None => return Err(c),
+                        },
+                    };
+                    out.push(b);
+                }
+            }
+            Encoding::ShiftJis => {
+                for c in chars.chars() {
+                    match c {
+                        '\0'..='\x7f' => out.push(c as u8),
+                        '\u{ff61}'..='\u{ff9f}' => out.push((c as u32 - 0xff61) as u8 + 0xa1),
+                        c => out.extend_from_slice(&shift_jis_code(c).ok_or(c)?),
+                    }
+                }
+            }
+        }
+        Ok(Cow::Owned(out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(encoding: Encoding) -> Format {
+        Format {
+            encoding,
+            bom: false,
+        }
+    }
+
+    #[test]
+    fn detect_bom() {
+        let cases = [
+            (&b"\xef\xbb\xbfa"[..], Encoding::Utf8, true),
+            (b"\xff\xfea\0", Encoding::Utf16Le, true),
+            (b"\xfe\xff\0a", Encoding::Utf16Be, true),
+            (b"a", Encoding::Latin1, false),
+        ];
+        for (raw, encoding, bom) in cases {
+            assert_eq!(
+                Format::detect(raw, Encoding::Latin1),
+                Format { encoding, bom },
+                "{:?}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let cases: [(Format, &[u8], &str); 7] = [
+            (format(Encoding::Utf8), b"caf\xc3\xa9", "café"),
+            (
+                Format::detect(b"\xef\xbb\xbf", Encoding::Utf8),
+                b"\xef\xbb\xbfa",
+                "a",
+            ),
+            (format(Encoding::Utf16Le), b"a\0\xe9\0=\xd8\0\xde", "aé😀"),
+            (format(Encoding::Utf16Be), b"\0a\0\xe9\xd8=\xde\0", "aé😀"),
+            (format(Encoding::Latin1), b"caf\xe9", "café"),
+            (format(Encoding::Windows1252), b"\x80 \x93a\x94", "€ “a”"),
+            (
+                format(Encoding::ShiftJis),
+                b"\x93\xfa\x96\x7b\xb6\x81\x5b\x81\x80\x81\xac\xea\xa4a",
+                "日本ｶー÷〓熙a",
+            ),
+        ];
+        for (format, raw, text) in cases {
+            let decoded = format.decode(raw.to_vec()).unwrap();
+            assert_eq!(std::str::from_utf8(&decoded).unwrap(), text, "{:?}", format);
+            assert_eq!(&*format.encode(&decoded).unwrap(), raw, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert_eq!(format(Encoding::Utf16Le).decode(b"a\0b".to_vec()), None);
+        // An unpaired surrogate.
+        assert_eq!(format(Encoding::Utf16Le).decode(b"=\xd8".to_vec()), None);
+        // A truncated double byte code, and one that isn't assigned.
+        assert_eq!(format(Encoding::ShiftJis).decode(b"a\x93".to_vec()), None);
+        assert_eq!(
+            format(Encoding::ShiftJis).decode(b"\x85\x40".to_vec()),
+            None
+        );
+        assert_eq!(format(Encoding::ShiftJis).decode(b"\x80".to_vec()), None);
+    }
+
+    #[test]
+    fn unrepresentable() {
+        assert_eq!(format(Encoding::Latin1).encode("€".as_bytes()), Err('€'));
+        assert_eq!(
+            format(Encoding::Windows1252).encode("Ā".as_bytes()),
+            Err('Ā')
+        );
+        assert_eq!(
+            format(Encoding::ShiftJis).encode("😀".as_bytes()),
+            Err('😀')
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// A text to match, which in line ending aware mode has its CRLF line
+/// endings turned into LF while remembering where they were.
+pub struct Text<'a> {
+    raw: &'a [u8],
+    lf: Cow<'a, [u8]>,
+    /// Offsets in `lf` of the line feeds which were preceded by a `\r`.
+    crlf: Vec<usize>,
+    /// Whether most lines end with CRLF rather than LF.
+    dominant_crlf: bool,
+}
+
+impl<'a> Text<'a> {
+    pub fn new(raw: &'a [u8], aware: bool) -> Self {
+        let mut text = Text {
+            raw,
+            lf: Cow::Borrowed(raw),
+            crlf: Vec::new(),
+            dominant_crlf: false,
+        };
+        if !aware || !raw.windows(2).any(|w| w == b"\r\n") {
+            return text;
+        }
+
+        let mut lf = Vec::with_capacity(raw.len());
+        let mut lines = 0;
+        for (i, &b) in raw.iter().enumerate() {
+            if b == b'\r' && raw.get(i + 1) == Some(&b'\n') {
+                text.crlf.push(lf.len());
+                continue;
+            }
+            lines += (b == b'\n') as usize;
+            lf.push(b);
+        }
+        text.dominant_crlf = text.crlf.len() * 2 > lines;
+        text.lf = Cow::Owned(lf);
+        text
+    }
+
+    /// Returns the original content.
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    /// Returns the content to match against.
+    pub fn matched(&self) -> &[u8] {
+        &self.lf
+    }
+
+    /// Returns the original bytes of `range` of the matched content. The `\r`
+    /// of a CRLF goes along with its line feed.
+    pub fn original(&self, range: Range<usize>) -> &'a [u8] {
+        let removed = |at: usize| self.crlf.partition_point(|&i| i < at);
+        &self.raw[range.start + removed(range.start)..range.end + removed(range.end)]
+    }
+
+    /// Returns `replacement` with its bare line feeds turned into CRLF if
+    /// that is the dominant line ending.
+    pub fn with_line_endings<'r>(&self, replacement: &'r [u8]) -> Cow<'r, [u8]> {
+        if !self.dominant_crlf || !replacement.contains(&b'\n') {
+            return Cow::Borrowed(replacement);
+        }
+        let mut out = Vec::with_capacity(replacement.len() + 8);
+        for (i, &b) in replacement.iter().enumerate() {
+            if b == b'\n' && (i == 0 || replacement[i - 1] != b'\r') {
+                out.push(b'\r');
+            }
+            out.push(b);
+        }
+        Cow::Owned(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crlf_turned_into_lf() {
+        let text = Text::new(b"a\r\nb\nc\r\n", true);
+        assert_eq!(text.matched(), b"a\nb\nc\n");
+        assert_eq!(text.raw(), b"a\r\nb\nc\r\n");
+        // The `\r` goes along with its line feed.
+        assert_eq!(text.original(0..2), b"a\r\n");
+        assert_eq!(text.original(1..1), b"");
+        assert_eq!(text.original(2..5), b"b\nc");
+        assert_eq!(text.original(5..6), b"\r\n");
+        let text = Text::new(b"a\r\nb\n", false);
+        assert_eq!(text.matched(), b"a\r\nb\n");
+    }
+
+    #[test]
+    fn dominant_line_ending() {
+        let text = Text::new(b"a\r\nb\r\nc\n", true);
+        assert_eq!(&*text.with_line_endings(b"x\ny\r\n"), b"x\r\ny\r\n");
+        let text = Text::new(b"a\r\nb\nc\n", true);
+        assert_eq!(&*text.with_line_endings(b"x\ny"), b"x\ny");
+    }
+}
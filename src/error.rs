@@ -0,0 +1,85 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// Exit status of a run which changed something, or found matches.
+pub const EXIT_CHANGED: u8 = 0;
+/// Exit status of a run which had nothing to do.
+pub const EXIT_NO_MATCH: u8 = 1;
+/// Exit status of a run in which any error occurred.
+pub const EXIT_ERROR: u8 = 2;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The pattern is not a valid regex.
+    Regex(regex::Error),
+    /// A glob is not valid.
+    Glob(String, glob::PatternError),
+    /// Traversing a directory tree failed.
+    Walk(walkdir::Error),
+    /// `action` failed on the file at `path`.
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        err: io::Error,
+    },
+    /// Anything else, described by the message.
+    Msg(String),
+}
+
+impl Error {
+    pub fn io(action: &'static str, path: impl Into<PathBuf>, err: io::Error) -> Self {
+        Error::Io {
+            action,
+            path: path.into(),
+            err,
+        }
+    }
+
+    /// Prints the error and returns the matching exit status.
+    pub fn report(&self) -> ExitCode {
+        eprintln!("error: {}", self);
+        ExitCode::from(EXIT_ERROR)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // The syntax errors of the regex crate already point at the
+            // offending part of the pattern.
+            Error::Regex(err) => write!(f, "{}", err),
+            Error::Glob(glob, err) => write!(
+                f,
+                "glob parse error:\n    {}\n    {:>width$}\nerror: {}",
+                glob,
+                "^",
+                err.msg,
+                width = err.pos + 1
+            ),
+            Error::Walk(err) => match (err.path(), err.io_error()) {
+                (Some(path), Some(io)) => write!(f, "failed to walk {:?}: {}", path, io),
+                _ => write!(f, "{}", err),
+            },
+            Error::Io { action, path, err } => {
+                write!(f, "failed to {} {:?}: {}", action, path, err)
+            }
+            Error::Msg(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<regex::Error> for Error {
+    fn from(err: regex::Error) -> Self {
+        Error::Regex(err)
+    }
+}
+
+impl From<walkdir::Error> for Error {
+    fn from(err: walkdir::Error) -> Self {
+        Error::Walk(err)
+    }
+}
@@ -0,0 +1,47 @@
+use regex::bytes::Regex;
+use std::fmt::Write;
+
+/// Returns a `name:line:col: text` line for each match of `re` in `cnt`,
+/// with the match highlighted if `color` is set.
+pub fn matches(name: &str, cnt: &[u8], re: &Regex, color: bool) -> String {
+    let (name_color, num_color, match_color, reset) = if color {
+        ("\x1b[35m", "\x1b[32m", "\x1b[1;31m", "\x1b[0m")
+    } else {
+        ("", "", "", "")
+    };
+    let mut out = String::new();
+    // Line number of `counted`, kept up to date to avoid rescanning the file.
+    let (mut line, mut counted) = (1, 0);
+
+    for m in re.find_iter(cnt) {
+        line += cnt[counted..m.start()]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count();
+        counted = m.start();
+        let line_start = cnt[..m.start()]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| i + 1);
+        let line_end = cnt[m.start()..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(cnt.len(), |i| m.start() + i);
+        let before = String::from_utf8_lossy(&cnt[line_start..m.start()]);
+        let col = before.chars().count() + 1;
+        // Only the first line of a match spanning several is shown.
+        let end = m.end().min(line_end);
+
+        let _ = writeln!(
+            out,
+            "{name_color}{}{reset}:{num_color}{}{reset}:{num_color}{}{reset}: {}{match_color}{}{reset}{}",
+            name,
+            line,
+            col,
+            before,
+            String::from_utf8_lossy(&cnt[m.start()..end]),
+            String::from_utf8_lossy(&cnt[end..line_end]),
+        );
+    }
+    out
+}
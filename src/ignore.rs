@@ -0,0 +1,328 @@
+use glob::{MatchOptions, Pattern};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::DirEntry;
+
+/// Ignore files looked up in every directory, by decreasing precedence.
+const IGNORE_FILES: [&str; 3] = [".jetignore", ".ignore", ".gitignore"];
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A single line of an ignore file.
+struct Rule {
+    pattern: Pattern,
+    /// Whitelists the paths matched, `!` in the ignore file.
+    negate: bool,
+    /// Only matches directories, trailing `/` in the ignore file.
+    dir_only: bool,
+    /// Matched against the path relative to the ignore file rather than
+    /// against the file name, for patterns containing a `/`.
+    anchored: bool,
+}
+
+/// Turns a gitignore pattern into one the glob crate understands: escapes
+/// become character classes and `**` not spanning a whole component is `*`.
+fn to_glob(pat: &str) -> String {
+    let chars: Vec<char> = pat.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                out.push('[');
+                out.push(chars[i]);
+                out.push(']');
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let whole = (i == 0 || chars[i - 1] == '/')
+                    && (i + 2 == chars.len() || chars[i + 2] == '/');
+                out.push_str(if whole { "**" } else { "*" });
+                while chars.get(i + 1) == Some(&'*') {
+                    i += 1;
+                }
+            }
+            c => out.push(c),
+        }
+        i += 1;
+    }
+    out
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        // Trailing spaces are ignored unless escaped.
+        let trimmed = line.trim_end_matches(' ');
+        let line = if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+            &line[..trimmed.len() + 1]
+        } else {
+            trimmed
+        };
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+        Some(Rule {
+            pattern: Pattern::new(&to_glob(line)).ok()?,
+            negate,
+            dir_only,
+            anchored,
+        })
+    }
+}
+
+/// The rules of the ignore files found in a directory.
+struct Gitignore {
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl Gitignore {
+    /// Reads the rules of `files`, relative to `base`.
+    fn from_files(base: &Path, files: &[PathBuf]) -> Option<Gitignore> {
+        let mut rules = Vec::new();
+        // Within a directory the files with higher precedence come first,
+        // while within a file the last matching rule wins, so the files are
+        // read in reverse and the rules checked from the end.
+        for file in files.iter().rev() {
+            if let Ok(cnt) = fs::read_to_string(file) {
+                rules.extend(cnt.lines().filter_map(Rule::parse));
+            }
+        }
+        if rules.is_empty() {
+            return None;
+        }
+        Some(Gitignore {
+            base: base.to_path_buf(),
+            rules,
+        })
+    }
+
+    fn in_dir(dir: &Path) -> Option<Gitignore> {
+        let files: Vec<PathBuf> = IGNORE_FILES.iter().map(|f| dir.join(f)).collect();
+        Gitignore::from_files(dir, &files)
+    }
+
+    /// Returns whether `path` is ignored (`Some(true)`), whitelisted
+    /// (`Some(false)`) or not matched at all.
+    fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let rel = path.strip_prefix(&self.base).ok()?;
+        let name = rel.file_name()?.to_str()?;
+        let rel = rel.to_str()?.replace('\\', "/");
+        self.rules
+            .iter()
+            .rev()
+            .filter(|r| is_dir || !r.dir_only)
+            .find(|r| {
+                let target = if r.anchored { rel.as_str() } else { name };
+                r.pattern.matches_with(target, MATCH_OPTIONS)
+            })
+            .map(|r| !r.negate)
+    }
+}
+
+/// Returns the global excludes file configured for git.
+fn global_excludes() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.as_ref().map(|h| h.join(".config")));
+
+    let configs = [
+        home.as_ref().map(|h| h.join(".gitconfig")),
+        config_home.as_ref().map(|c| c.join("git").join("config")),
+    ];
+    for config in configs.iter().flatten() {
+        let Ok(cnt) = fs::read_to_string(config) else {
+            continue;
+        };
+        let mut in_core = false;
+        for line in cnt.lines().map(str::trim) {
+            if line.starts_with('[') {
+                in_core = line.eq_ignore_ascii_case("[core]");
+            } else if let Some((key, value)) = line.split_once('=') {
+                if in_core && key.trim().eq_ignore_ascii_case("excludesfile") {
+                    let value = value.trim().trim_matches('"');
+                    return match (value.strip_prefix("~/"), &home) {
+                        (Some(rest), Some(home)) => Some(home.join(rest)),
+                        _ => Some(PathBuf::from(value)),
+                    };
+                }
+            }
+        }
+    }
+    config_home.map(|c| c.join("git").join("ignore"))
+}
+
+/// Tracks the ignore rules applying to the entries of a directory walk.
+pub struct Ignore {
+    root: PathBuf,
+    root_abs: PathBuf,
+    /// Rules from the directories above the root, up to the repository
+    /// root, and the repository and global excludes, by decreasing precedence.
+    outer: Vec<Gitignore>,
+    /// Rules from the directories of the walk, indexed by depth.
+    stack: Vec<Option<Gitignore>>,
+}
+
+impl Ignore {
+    pub fn new(root: &Path) -> Self {
+        let root_abs = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+        let mut outer = Vec::new();
+
+        let repo = root_abs.ancestors().find(|d| d.join(".git").exists());
+        if let Some(repo) = repo {
+            for dir in root_abs.ancestors().skip(1) {
+                if !dir.starts_with(repo) {
+                    break;
+                }
+                outer.extend(Gitignore::in_dir(dir));
+            }
+            let exclude = repo.join(".git").join("info").join("exclude");
+            outer.extend(Gitignore::from_files(repo, &[exclude]));
+        }
+        let base = repo.unwrap_or(&root_abs);
+        if let Some(global) = global_excludes() {
+            outer.extend(Gitignore::from_files(base, &[global]));
+        }
+
+        Ignore {
+            root: root.to_path_buf(),
+            root_abs,
+            outer,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Returns whether the walk should go through `entry`, keeping track of
+    /// the ignore files of the directories it enters. Entries must be given
+    /// in the order of the walk, parents before their content.
+    pub fn keep(&mut self, entry: &DirEntry) -> bool {
+        let depth = entry.depth();
+        self.stack.truncate(depth);
+        let rel = entry
+            .path()
+            .strip_prefix(&self.root)
+            .unwrap_or(entry.path());
+        let path = self.root_abs.join(rel);
+        let is_dir = entry.file_type().is_dir();
+
+        // The roots given explicitly are never ignored.
+        if depth > 0 {
+            let ignored = self
+                .stack
+                .iter()
+                .rev()
+                .flatten()
+                .chain(&self.outer)
+                .find_map(|g| g.matched(&path, is_dir));
+            if ignored == Some(true) {
+                return false;
+            }
+        }
+        if is_dir {
+            self.stack.push(Gitignore::in_dir(&path));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gitignore(lines: &str) -> Gitignore {
+        Gitignore {
+            base: PathBuf::from("/r"),
+            rules: lines.lines().filter_map(Rule::parse).collect(),
+        }
+    }
+
+    #[test]
+    fn to_glob_escapes_and_stars() {
+        assert_eq!(to_glob(r"\#a\*"), "[#]a[*]");
+        assert_eq!(to_glob("**/a/**"), "**/a/**");
+        assert_eq!(to_glob("a**b"), "a*b");
+        // Other runs of asterisks are plain ones.
+        assert_eq!(to_glob("a/***"), "a/*");
+    }
+
+    #[test]
+    fn parse_rules() {
+        for line in ["", "# comment", "!", "/", "   "] {
+            assert!(Rule::parse(line).is_none(), "{:?}", line);
+        }
+        let rule = Rule::parse("!build/\r").unwrap();
+        assert!(rule.negate && rule.dir_only && !rule.anchored);
+        assert_eq!(rule.pattern.as_str(), "build");
+        let rule = Rule::parse("/target").unwrap();
+        assert!(!rule.negate && !rule.dir_only && rule.anchored);
+        assert_eq!(rule.pattern.as_str(), "target");
+        assert_eq!(Rule::parse("a  ").unwrap().pattern.as_str(), "a");
+        assert_eq!(Rule::parse(r"a\  ").unwrap().pattern.as_str(), "a[ ]");
+    }
+
+    #[test]
+    fn matched_names_and_paths() {
+        let ignore = gitignore("*.log\n/out\ndocs/*.md\nbuild/\n");
+        let cases = [
+            ("/r/x.log", false, Some(true)),
+            ("/r/sub/x.log", false, Some(true)),
+            ("/r/out", false, Some(true)),
+            ("/r/sub/out", false, None),
+            ("/r/docs/a.md", false, Some(true)),
+            ("/r/docs/sub/a.md", false, None),
+            ("/r/build", true, Some(true)),
+            ("/r/build", false, None),
+            ("/r/main.rs", false, None),
+            ("/elsewhere/x.log", false, None),
+        ];
+        for (path, is_dir, expected) in cases {
+            assert_eq!(
+                ignore.matched(Path::new(path), is_dir),
+                expected,
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn last_rule_wins() {
+        let ignore = gitignore("*.log\n!keep.log\n");
+        assert_eq!(ignore.matched(Path::new("/r/a.log"), false), Some(true));
+        assert_eq!(ignore.matched(Path::new("/r/keep.log"), false), Some(false));
+        let ignore = gitignore("!keep.log\n*.log\n");
+        assert_eq!(ignore.matched(Path::new("/r/keep.log"), false), Some(true));
+    }
+
+    #[test]
+    fn file_precedence() {
+        let dir = env::temp_dir().join(format!("jet-ignore-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(dir.join(".jetignore"), "!keep.log\n").unwrap();
+        let ignore = Gitignore::in_dir(&dir).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(ignore.matched(&dir.join("a.log"), false), Some(true));
+        assert_eq!(ignore.matched(&dir.join("keep.log"), false), Some(false));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::eol::Text;
+use crate::replace::{self, Action, Counts, Replacement};
+use regex::bytes::Regex;
+use std::borrow::Cow;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::ops::Range;
+use std::path::Path;
+
+/// The user's decision about a single match.
+enum Answer {
+    Yes,
+    No,
+    All,
+    Quit,
+}
+
+fn ask() -> Answer {
+    let mut stdin = io::stdin().lock();
+    loop {
+        eprint!("Replace? [y]es, [n]o, [a]ll in this file, [q]uit: ");
+        let mut line = String::new();
+        match stdin.read_line(&mut line) {
+            Ok(0) | Err(_) => return Answer::Quit,
+            Ok(_) => {}
+        }
+        match line.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Answer::Yes,
+            "n" | "no" => return Answer::No,
+            "a" | "all" => return Answer::All,
+            "q" | "quit" => return Answer::Quit,
+            _ => {}
+        }
+    }
+}
+
+/// Prints the lines spanned by `cnt[start..end]` with up to `context` lines
+/// around them, followed by the same lines with `replacement` in place.
+fn show(
+    path: &Path,
+    line: usize,
+    cnt: &[u8],
+    start: usize,
+    end: usize,
+    replacement: &[u8],
+    context: usize,
+) {
+    let (red, green, reset) = if io::stderr().is_terminal() {
+        ("\x1b[31m", "\x1b[32m", "\x1b[0m")
+    } else {
+        ("", "", "")
+    };
+    let line_start = cnt[..start]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let line_end = cnt[end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(cnt.len(), |i| end + i + 1);
+    let before: Vec<&[u8]> = cnt[..line_start]
+        .split_inclusive(|&b| b == b'\n')
+        .rev()
+        .take(context)
+        .collect();
+    let after = cnt[line_end..]
+        .split_inclusive(|&b| b == b'\n')
+        .take(context);
+    let new = [&cnt[line_start..start], replacement, &cnt[end..line_end]].concat();
+
+    let mut out = format!("{}:{}:\n", path.display(), line);
+    let mut push = |sign: char, text: &[u8], color: &str| {
+        for l in text.split_inclusive(|&b| b == b'\n') {
+            let l = String::from_utf8_lossy(l);
+            out.push_str(&format!(
+                "{}{}{}{}",
+                color,
+                sign,
+                l.trim_end_matches('\n'),
+                reset
+            ));
+            out.push('\n');
+        }
+    };
+    before.iter().rev().for_each(|l| push(' ', l, ""));
+    push('-', &cnt[line_start..line_end], red);
+    push('+', &new, green);
+    after.for_each(|l| push(' ', l, ""));
+    let _ = io::stderr().lock().write_all(out.as_bytes());
+}
+
+/// Replaces the matches of `re` in `text`, within `region` if given, which
+/// the user accepts, also returning whether they asked to quit.
+pub fn replace<'a>(
+    path: &Path,
+    text: &Text<'a>,
+    re: &Regex,
+    replacement: &Replacement,
+    region: Option<&[Range<usize>]>,
+    context: usize,
+) -> (Cow<'a, [u8]>, Counts, bool) {
+    let mut all = false;
+    let mut quit = false;
+    // Line number of `counted`, kept up to date to avoid rescanning the file.
+    let (mut line, mut counted) = (1, 0);
+    let cnt = text.matched();
+
+    let (modified, counts) = replace::replace(text, re, replacement, region, |m, dst| {
+        if all || dst == m.as_bytes() {
+            return Action::Replace;
+        }
+        line += cnt[counted..m.start()]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count();
+        counted = m.start();
+        show(path, line, cnt, m.start(), m.end(), dst, context);
+        match ask() {
+            Answer::Yes => Action::Replace,
+            Answer::No => Action::Skip,
+            Answer::All => {
+                all = true;
+                Action::Replace
+            }
+            Answer::Quit => {
+                quit = true;
+                Action::Stop
+            }
+        }
+    });
+    (modified, counts, quit)
+}
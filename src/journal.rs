@@ -0,0 +1,262 @@
+use crate::atomic;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of runs kept in the journal, older ones are pruned.
+const KEEP_RUNS: usize = 16;
+const MANIFEST: &str = "manifest";
+
+/// Returns the default journal directory, following the XDG base directories.
+pub fn default_dir() -> Option<PathBuf> {
+    let var = |name| {
+        env::var_os(name)
+            .map(PathBuf::from)
+            .filter(|d| d.is_absolute())
+    };
+    let state = if cfg!(windows) {
+        var("LOCALAPPDATA")
+    } else {
+        var("XDG_STATE_HOME").or_else(|| var("HOME").map(|h| h.join(".local").join("state")))
+    };
+    state.map(|d| d.join("jet").join("journal"))
+}
+
+/// Hash of empty content, to start from when hashing content seen in parts.
+pub const HASH_INIT: u64 = 0xcbf29ce484222325;
+
+/// 64-bit FNV-1a, stable across platforms and releases unlike `DefaultHasher`.
+pub fn hash(data: &[u8]) -> u64 {
+    hash_more(HASH_INIT, data)
+}
+
+/// Returns the hash `h` of some content followed by `data`.
+pub fn hash_more(h: u64, data: &[u8]) -> u64 {
+    data.iter()
+        .fold(h, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+struct Run {
+    dir: PathBuf,
+    manifest: File,
+    blobs: usize,
+}
+
+/// Records the original content of every file rewritten by a run, so that
+/// the run can be undone later.
+///
+/// The run directory is only created once the first file is recorded.
+pub struct Journal {
+    root: PathBuf,
+    command: String,
+    run: Mutex<Option<Run>>,
+}
+
+impl Journal {
+    pub fn new(root: PathBuf) -> Self {
+        let command = env::args().collect::<Vec<_>>().join(" ");
+        Journal {
+            root,
+            command,
+            run: Mutex::new(None),
+        }
+    }
+
+    fn start(&self) -> io::Result<Run> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let dir = self.root.join(format!("{}-{}", secs, process::id()));
+        fs::create_dir_all(&dir)?;
+        let mut manifest = File::create(dir.join(MANIFEST))?;
+        writeln!(manifest, "{}", escape(&self.command))?;
+        prune(&self.root);
+        Ok(Run {
+            dir,
+            manifest,
+            blobs: 0,
+        })
+    }
+
+    /// Records that `path`, currently holding `original`, is about to be
+    /// rewritten with `modified`.
+    pub fn record(&self, path: &Path, original: &[u8], modified: &[u8]) -> io::Result<()> {
+        self.add(path, hash(original), hash(modified), |blob| {
+            fs::write(blob, original)
+        })
+    }
+
+    /// Records that `path`, whose content hashes to `original`, is about to be
+    /// replaced by a new file with content hashing to `modified`. The original
+    /// is kept by linking to it, which is only safe as it gets replaced
+    /// rather than rewritten in place.
+    pub fn record_file(&self, path: &Path, original: u64, modified: u64) -> io::Result<()> {
+        self.add(path, original, modified, |blob| {
+            if fs::hard_link(path, blob).is_err() {
+                fs::copy(path, blob)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn add<F>(&self, path: &Path, original: u64, modified: u64, save: F) -> io::Result<()>
+    where
+        F: FnOnce(&Path) -> io::Result<()>,
+    {
+        let path = fs::canonicalize(path)?;
+        let name = path
+            .to_str()
+            .ok_or_else(|| invalid(format!("cannot journal non UTF-8 path {:?}", path)))?;
+
+        let (blob, dir) = {
+            let mut run = self.run.lock().unwrap();
+            if run.is_none() {
+                *run = Some(self.start()?);
+            }
+            let run = run.as_mut().unwrap();
+            run.blobs += 1;
+            (run.blobs, run.dir.clone())
+        };
+        save(&dir.join(blob.to_string()))?;
+
+        let line = format!(
+            "{:016x}\t{:016x}\t{}\t{}\n",
+            original,
+            modified,
+            blob,
+            escape(name)
+        );
+        let mut run = self.run.lock().unwrap();
+        let manifest = &mut run.as_mut().unwrap().manifest;
+        manifest.write_all(line.as_bytes())?;
+        manifest.sync_data()
+    }
+}
+
+/// Returns the recorded runs in `root`, oldest first.
+pub fn runs(root: &Path) -> Vec<String> {
+    let mut runs: Vec<(u64, u64, String)> = fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|e| e.file_name().into_string().ok())
+        .filter_map(|id| {
+            let (secs, pid) = id.split_once('-')?;
+            Some((secs.parse().ok()?, pid.parse().ok()?, id))
+        })
+        .collect();
+    runs.sort();
+    runs.into_iter().map(|(_, _, id)| id).collect()
+}
+
+fn prune(root: &Path) {
+    let runs = runs(root);
+    if runs.len() > KEEP_RUNS {
+        for id in &runs[..runs.len() - KEEP_RUNS] {
+            let _ = fs::remove_dir_all(root.join(id));
+        }
+    }
+}
+
+/// A file rewritten by a run.
+pub struct Entry {
+    pub path: PathBuf,
+    original: u64,
+    modified: u64,
+    blob: PathBuf,
+}
+
+/// The state of a recorded file compared to the run that rewrote it.
+#[derive(PartialEq)]
+pub enum State {
+    /// Still holds the content written by the run.
+    Modified,
+    /// Already holds its original content.
+    Original,
+    /// Was changed or removed since the run.
+    Changed,
+}
+
+impl Entry {
+    pub fn state(&self) -> State {
+        match fs::read(&self.path).map(|cnt| hash(&cnt)) {
+            Ok(h) if h == self.modified => State::Modified,
+            Ok(h) if h == self.original => State::Original,
+            _ => State::Changed,
+        }
+    }
+
+    /// Puts the original content back in place.
+    pub fn restore(&self) -> io::Result<()> {
+        let original = fs::read(&self.blob)?;
+        if hash(&original) != self.original {
+            return Err(invalid(format!("corrupted journal entry {:?}", self.blob)));
+        }
+        atomic::write(&self.path, &original, false)
+    }
+}
+
+/// Reads the command line and the entries of the run `id`.
+pub fn load(root: &Path, id: &str) -> io::Result<(String, Vec<Entry>)> {
+    let dir = root.join(id);
+    let manifest = BufReader::new(File::open(dir.join(MANIFEST))?);
+    let mut lines = manifest.lines();
+    let command = unescape(&lines.next().transpose()?.unwrap_or_default());
+
+    let mut entries = Vec::new();
+    for line in lines {
+        let line = line?;
+        let bad = || invalid(format!("malformed journal line {:?}", line));
+        let mut fields = line.splitn(4, '\t');
+        let mut next = || fields.next().ok_or_else(bad);
+        let original = u64::from_str_radix(next()?, 16).map_err(|_| bad())?;
+        let modified = u64::from_str_radix(next()?, 16).map_err(|_| bad())?;
+        let blob = dir.join(next()?);
+        let path = PathBuf::from(unescape(next()?));
+        entries.push(Entry {
+            path,
+            original,
+            modified,
+            blob,
+        });
+    }
+    Ok((command, entries))
+}
+
+/// Removes the run `id` from the journal.
+pub fn remove(root: &Path, id: &str) -> io::Result<()> {
+    fs::remove_dir_all(root.join(id))
+}
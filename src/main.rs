@@ -1,109 +1,838 @@
-use clap::Parser;
-use glob::Pattern;
+mod address;
+mod atomic;
+mod backup;
+mod binary;
+mod case;
+mod diff;
+mod encoding;
+mod eol;
+mod error;
+mod find;
+mod ignore;
+mod interactive;
+mod jis0208;
+mod journal;
+mod pattern;
+mod replace;
+mod stats;
+mod stream;
+mod walk;
+
+use address::{Address, Region};
+use backup::Backup;
+use clap::builder::NonEmptyStringValueParser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use encoding::{Encoding, Format};
+use eol::Text;
+use error::{Error, EXIT_CHANGED, EXIT_ERROR, EXIT_NO_MATCH};
+use journal::{Journal, State};
+use pattern::{Pattern, PatternOptions};
 use rayon::prelude::*;
-use regex::Regex;
-use std::fs::File;
-use std::io::{self, Read, Write};
-use walkdir::{DirEntry, WalkDir};
+use regex::bytes::{Regex, RegexBuilder};
+use replace::{Counts, Limits, Quota, Replacement};
+use stats::{Skip, Stats};
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use walk::WalkOptions;
 
 #[derive(Parser, Debug)]
-#[command(about, long_about = None)]
+#[command(
+    about,
+    long_about = None,
+    after_help = "The subcommands are only recognized as the first argument. To replace \
+                  the words `find` or `undo`, put `--` before the pattern, as in \
+                  `jet -- find X dir`; after any option they are taken as the pattern.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    opts: Option<Options>,
+    // These are not part of `Options`, as clap can't tell whether an
+    // optional flattened struct was given when it nests another one.
+    #[command(flatten)]
+    walk: WalkOptions,
+    #[command(flatten)]
+    pattern: PatternOptions,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the matches of a pattern, selecting the files like a replacement would.
+    Find(FindOptions),
+    /// Restore the files edited by a previous run.
+    Undo(UndoOptions),
+}
+
+#[derive(Args, Debug)]
 struct Options {
     pattern: String,
     replacement: String,
-    path: String,
-    /// Add a glob the file names must match to be edited.
-    #[arg(short, long)]
-    glob: Option<String>,
+    /// Files and directories to edit, `-` for stdin.
+    #[arg(required = true)]
+    paths: Vec<String>,
     /// Print to stdout instead of writing each file.
     #[arg(short = 'p', long = "print")]
     to_stdout: bool,
+    /// Print a unified diff of the changes instead of writing each file.
+    #[arg(short, long, conflicts_with = "to_stdout")]
+    diff: bool,
+    /// Lines of context around each change in the diff and interactive prompts.
+    #[arg(short = 'U', long = "context", default_value_t = 3)]
+    context: usize,
+    /// Insert the replacement as it is, without expanding `$` references to
+    /// the capture groups.
+    #[arg(long)]
+    literal_replacement: bool,
+    /// Match CRLF line endings as `\n` and `$`, and write the line feeds of the
+    /// replacements with the line ending most used in each file.
+    #[arg(long)]
+    crlf: bool,
+    /// Only replace the matches within the lines FIRST to LAST, counting from
+    /// 1, either being optional.
+    #[arg(long, value_name = "FIRST:LAST", value_parser = address::parse_lines)]
+    lines: Option<(usize, usize)>,
+    /// Only replace the matches within the sections starting at a line
+    /// matching REGEX, up to the end or the line matching `--to`.
+    #[arg(long, value_name = "REGEX", conflicts_with = "lines")]
+    from: Option<String>,
+    /// End the sections started by `--from` at the next line matching REGEX,
+    /// included.
+    #[arg(long, value_name = "REGEX", requires = "from")]
+    to: Option<String>,
+    /// Replace at most N matches in each file.
+    #[arg(long, value_name = "N", conflicts_with = "interactive")]
+    max_count: Option<usize>,
+    /// Replace at most N matches across all the files.
+    #[arg(long, value_name = "N", conflicts_with = "interactive")]
+    max_total: Option<usize>,
+    /// Only replace the K-th match in each file, counting from 1.
+    #[arg(
+        long,
+        value_name = "K",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["interactive", "max_count"]
+    )]
+    nth: Option<u64>,
+    /// Apply the pattern to each line on its own, without its line ending,
+    /// so that `^` and `$` match at its start and end.
+    #[arg(long)]
+    line_mode: bool,
+    /// Ask for confirmation before replacing each match.
+    #[arg(long, conflicts_with = "line_mode")]
+    interactive: bool,
+    /// Keep the original of each edited file, named after it plus SUFFIX.
+    #[arg(
+        short,
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ".bak",
+        value_parser = NonEmptyStringValueParser::new()
+    )]
+    backup: Option<String>,
+    /// Keep the original of each edited file under DIR, mirroring its absolute path.
+    #[arg(long, value_name = "DIR")]
+    backup_dir: Option<PathBuf>,
+    /// Keep the modification time of the edited files.
+    #[arg(long)]
+    preserve_mtime: bool,
+    /// Don't record the edited files in the undo journal.
+    #[arg(long)]
+    no_journal: bool,
+    /// Directory of the undo journal.
+    #[arg(long, value_name = "DIR")]
+    journal_dir: Option<PathBuf>,
+    /// Print the number of matches and replacements per file and in total.
+    #[arg(long)]
+    stats: bool,
+    /// Print the statistics as JSON.
+    #[arg(long)]
+    json: bool,
+    /// Process the files larger than SIZE a block of lines at a time, if the
+    /// pattern can't match across lines. Accepts K, M and G suffixes.
+    #[arg(long, value_name = "SIZE", default_value = "64M", value_parser = parse_size)]
+    stream_threshold: u64,
     /// Verbose, explain what is being done.
     #[arg(short, long)]
     verbose: bool,
-    /// Max depth in a directory tree.
-    #[arg(short = 'l', long = "level", default_value_t = -1)]
-    depth: i32,
-    /// Includes hidden files (starting with a dot).
-    #[arg(short = 'a', long = "all")]
-    include_hidden: bool,
-}
-
-fn is_hidden(entry: &DirEntry) -> bool {
-    entry
-        .file_name()
-        .to_str()
-        .map(|s| s.starts_with("."))
-        .unwrap_or(false)
-}
-
-fn process_file(
-    entry: walkdir::DirEntry,
-    re: &regex::Regex,
-    replacement: &String,
+}
+
+/// Parses a size in bytes, optionally followed by a K, M or G multiplier.
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, shift) = match s.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&s[..i], 10),
+        Some((i, 'M' | 'm')) => (&s[..i], 20),
+        Some((i, 'G' | 'g')) => (&s[..i], 30),
+        _ => (s, 0),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("invalid size {:?}", s))
+}
+
+#[derive(Args, Debug)]
+struct FindOptions {
+    pattern: String,
+    /// Files and directories to search, `-` for stdin.
+    #[arg(required = true)]
+    paths: Vec<String>,
+    #[command(flatten)]
+    walk: WalkOptions,
+    #[command(flatten)]
+    pattern_opts: PatternOptions,
+    /// Only print the paths of the files with matches.
+    #[arg(long)]
+    files_with_matches: bool,
+    /// When to highlight the matches.
+    #[arg(long, value_enum, default_value_t = Color::Auto)]
+    color: Color,
+    /// Verbose, explain what is being done.
+    #[arg(short, long)]
     verbose: bool,
-    to_stdout: bool,
-) {
-    let path = entry.path();
+}
 
-    if let Ok(mut file) = File::open(&path) {
-        let mut cnt = String::new();
-        if let Err(err) = file.read_to_string(&mut cnt) {
-            if verbose {
-                eprintln!("error: failed to read file {:?}: {}", path, err);
-            }
-            return;
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Args, Debug)]
+struct UndoOptions {
+    /// The run to undo, the last one if omitted.
+    run: Option<String>,
+    /// List the recorded runs instead.
+    #[arg(long, conflicts_with = "run")]
+    list: bool,
+    /// Restore the files even if they were changed since the run.
+    #[arg(short, long)]
+    force: bool,
+    /// Directory of the undo journal.
+    #[arg(long, value_name = "DIR")]
+    journal_dir: Option<PathBuf>,
+    /// Verbose, explain what is being done.
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+/// State shared by all the files processed in a run.
+struct Context<'a> {
+    opts: &'a Options,
+    walk: &'a WalkOptions,
+    re: Regex,
+    replacement: Replacement,
+    /// Whether large files can be processed a block of lines at a time.
+    stream: bool,
+    address: Option<Address>,
+    limits: Limits,
+    backup: Option<Backup>,
+    journal: Option<Journal>,
+    /// Set when the user quits an interactive run.
+    quit: AtomicBool,
+    stats: Stats,
+}
+
+/// Returns the name `path` is given in diff headers, without `./` components.
+fn diff_name(prefix: &str, path: &Path) -> String {
+    let path: PathBuf = path
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .collect();
+    format!("{}{}", prefix, path.display())
+}
+
+fn print_diff(old: &[u8], new: &[u8], old_name: &str, new_name: &str, context: usize) {
+    let diff = diff::unified(old, new, old_name, new_name, context);
+    if !diff.is_empty() {
+        // Write each diff in one go so parallel workers don't interleave.
+        let _ = io::stdout().lock().write_all(&diff);
+    }
+}
+
+/// Reads the file at `path` as UTF-8 along with its format, or returns why
+/// it is skipped: binary files unless asked for, and text which isn't valid
+/// in its encoding, UTF-8 outside of byte mode.
+fn read_file(
+    path: &Path,
+    walk: &WalkOptions,
+    verbose: bool,
+) -> Result<Result<(Vec<u8>, Format), Skip>, Error> {
+    let raw = fs::read(path).map_err(|err| Error::io("read", path, err))?;
+    let format = Format::detect(&raw, walk.encoding);
+    let skip = |skip: Skip| {
+        if verbose {
+            eprintln!("skipping {} file {:?}", skip, path);
         }
+        Ok(Err(skip))
+    };
+    // UTF-16 text is full of NUL bytes, only a signature tells it is binary.
+    let binary = if format.encoding.is_utf16() {
+        binary::has_magic(&raw)
+    } else {
+        binary::is_binary(&raw)
+    };
+    if binary && !walk.binary {
+        return skip(Skip::Binary);
+    }
+    let Some(cnt) = format.decode(raw) else {
+        // Counted along with the invalid UTF-8.
+        if verbose {
+            eprintln!(
+                "skipping {:?}, which is not valid {}",
+                path, format.encoding
+            );
+        }
+        return Ok(Err(Skip::NonUtf8));
+    };
+    let utf8 = format.encoding == Encoding::Utf8 && !walk.bytes;
+    if !binary && utf8 && std::str::from_utf8(&cnt).is_err() {
+        return skip(Skip::NonUtf8);
+    }
+    Ok(Ok((cnt, format)))
+}
 
-        let modified = re.replace_all(&cnt, replacement);
+/// Returns `cnt` in `format` to be written to the file at `path`.
+fn encode<'a>(cnt: &'a [u8], format: Format, path: &Path) -> Result<Cow<'a, [u8]>, Error> {
+    format.encode(cnt).map_err(|c| {
+        Error::Msg(format!(
+            "cannot write {:?} back in {}, which has no {:?}",
+            path, format.encoding, c
+        ))
+    })
+}
+
+/// Where the replacement of a file is at, carried over its blocks when it
+/// is streamed.
+struct Progress<'a> {
+    /// Number of the first line of the next block.
+    line: usize,
+    region: Option<Region<'a>>,
+    quota: Quota<'a>,
+}
 
-        if to_stdout {
-            println!("{}", modified);
-            return;
+impl<'a> Progress<'a> {
+    fn new(address: Option<&'a Address>, limits: &'a Limits) -> Self {
+        Progress {
+            line: 1,
+            region: address.map(Region::new),
+            quota: Quota::new(limits),
         }
+    }
+}
 
-        if let Ok(mut modified_file) = File::create(&path) {
-            if let Err(err) = modified_file.write_all(modified.as_bytes()) {
-                eprintln!("error: failed to write to file {:?}: {}", path, err);
-                return;
+/// Replaces the matches in `cnt`, the next block of a file, within its
+/// region and limits. In line mode each line is replaced on its own and the
+/// ones changed are listed in verbose mode.
+fn replace_all<'a>(
+    cnt: &'a [u8],
+    re: &Regex,
+    replacement: &Replacement,
+    opts: &Options,
+    path: &Path,
+    progress: &mut Progress,
+) -> (Cow<'a, [u8]>, Counts) {
+    let first = progress.line;
+    progress.line += cnt.iter().filter(|&&b| b == b'\n').count();
+    let (region, quota) = (&mut progress.region, &mut progress.quota);
+    if !opts.line_mode {
+        let text = Text::new(cnt, opts.crlf);
+        let region = region.as_mut().map(|r| r.ranges(text.matched()));
+        return replace::replace(&text, re, replacement, region.as_deref(), |m, dst| {
+            quota.decide(dst == m.as_bytes())
+        });
+    }
+    let region = region.as_mut().map(|r| r.ranges(cnt));
+    replace::replace_lines(
+        cnt,
+        re,
+        replacement,
+        opts.crlf,
+        region.as_deref(),
+        |m, dst| quota.decide(dst == m.as_bytes()),
+        |i| {
+            if opts.verbose {
+                eprintln!("{}:{}: changed", path.display(), first + i);
             }
-            if verbose {
-                println!("{:?} modified", path);
+        },
+    )
+}
+
+/// Processes the file at `path` a block of lines at a time, writing the
+/// result as it goes, or returns false if it has to be read whole because
+/// of its byte order mark.
+fn process_stream(path: &Path, ctx: &Context) -> Result<bool, Error> {
+    let opts = ctx.opts;
+    let file = File::open(path).map_err(|err| {
+        ctx.stats.skip(Skip::Unreadable);
+        Error::io("open", path, err)
+    })?;
+    let mut blocks = stream::Blocks::new(file);
+    let mut block = Vec::new();
+    let mut counts = Counts::default();
+    // Bytes of the original read, and hashes of the original and new content.
+    let (mut read, mut original, mut modified) = (0, journal::HASH_INIT, journal::HASH_INIT);
+    let mut out: Option<atomic::AtomicFile> = None;
+    let mut stdout = opts.to_stdout.then(|| io::stdout().lock());
+    let mut binary = false;
+    let mut progress = Progress::new(ctx.address.as_ref(), &ctx.limits);
+
+    while blocks
+        .read(&mut block)
+        .map_err(|err| Error::io("read", path, err))?
+    {
+        if read == 0 {
+            if Format::detect(&block, Encoding::Utf8).bom {
+                return Ok(false);
             }
+            binary = binary::is_binary(&block);
+        }
+        let utf8 = !binary && !ctx.walk.bytes;
+        let skip = if binary && !ctx.walk.binary {
+            Some(Skip::Binary)
         } else {
-            eprintln!("error: could not override file {:?}", path);
+            (utf8 && std::str::from_utf8(&block).is_err()).then_some(Skip::NonUtf8)
+        };
+        if let Some(skip) = skip {
+            // Only the blocks before are printed, which can't be taken back.
+            if stdout.is_some() && read > 0 {
+                return Err(Error::Msg(format!(
+                    "{:?} is {} past the {} bytes printed",
+                    path, skip, read
+                )));
+            }
+            if opts.verbose {
+                eprintln!("skipping {} file {:?}", skip, path);
+            }
+            ctx.stats.skip(skip);
+            return Ok(true);
+        }
+
+        let (new, c) = replace_all(&block, &ctx.re, &ctx.replacement, opts, path, &mut progress);
+        counts += c;
+        original = journal::hash_more(original, &block);
+        modified = journal::hash_more(modified, &new);
+        if let Some(stdout) = &mut stdout {
+            let _ = stdout.write_all(&new);
+        } else if out.is_none() && c.replacements > 0 {
+            // Only start the new file at the first change, with the lines
+            // before it copied as they are.
+            let mut file =
+                atomic::AtomicFile::create(path).map_err(|err| Error::io("write to", path, err))?;
+            File::open(path)
+                .and_then(|f| io::copy(&mut f.take(read), &mut file))
+                .map_err(|err| Error::io("write to", path, err))?;
+            out = Some(file);
         }
+        if let Some(out) = &mut out {
+            out.write_all(&new)
+                .map_err(|err| Error::io("write to", path, err))?;
+        }
+        read += block.len() as u64;
+    }
+
+    ctx.stats.file(path, counts);
+    let Some(out) = out else {
+        if opts.to_stdout && counts.replacements > 0 {
+            ctx.stats.modified();
+        }
+        return Ok(true);
+    };
+    if let Some(backup) = &ctx.backup {
+        backup
+            .save(path)
+            .map_err(|err| Error::io("back up", path, err))?;
     }
+    if let Some(journal) = &ctx.journal {
+        journal
+            .record_file(path, original, modified)
+            .map_err(|err| Error::io("journal", path, err))?;
+    }
+    out.commit(opts.preserve_mtime)
+        .map_err(|err| Error::io("write to", path, err))?;
+    if opts.verbose {
+        println!("{:?} modified", path);
+    }
+    ctx.stats.modified();
+    Ok(true)
 }
 
-fn process_stdin(re: &regex::Regex, replacement: &String) {
-    let mut cnt = String::new();
-    let mut stdin = io::stdin();
+fn process_file(entry: walkdir::DirEntry, ctx: &Context) -> Result<(), Error> {
+    let (opts, re) = (ctx.opts, &ctx.re);
+    let path = entry.path();
+    if ctx.quit.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    let large = fs::metadata(path).is_ok_and(|m| m.len() > opts.stream_threshold);
+    if ctx.stream && (large || opts.line_mode) && process_stream(path, ctx)? {
+        return Ok(());
+    }
+
+    let (cnt, format) = match read_file(path, ctx.walk, opts.verbose) {
+        Ok(Ok(read)) => read,
+        Ok(Err(skip)) => {
+            ctx.stats.skip(skip);
+            return Ok(());
+        }
+        Err(err) => {
+            ctx.stats.skip(Skip::Unreadable);
+            return Err(err);
+        }
+    };
+
+    let (modified, counts) = if opts.interactive {
+        let text = Text::new(&cnt, opts.crlf);
+        let region = ctx
+            .address
+            .as_ref()
+            .map(|a| Region::new(a).ranges(text.matched()));
+        let (modified, counts, quit) = interactive::replace(
+            path,
+            &text,
+            re,
+            &ctx.replacement,
+            region.as_deref(),
+            opts.context,
+        );
+        ctx.quit.store(quit, Ordering::Relaxed);
+        (modified, counts)
+    } else {
+        let mut progress = Progress::new(ctx.address.as_ref(), &ctx.limits);
+        replace_all(&cnt, re, &ctx.replacement, opts, path, &mut progress)
+    };
+    let changed = *modified != cnt;
+    ctx.stats.file(path, counts);
+    if changed && (opts.to_stdout || opts.diff) {
+        ctx.stats.modified();
+    }
+
+    if opts.to_stdout {
+        let _ = io::stdout()
+            .lock()
+            .write_all(&encode(&modified, format, path)?);
+        return Ok(());
+    }
+
+    if opts.diff {
+        let old_name = diff_name("a/", path);
+        let new_name = diff_name("b/", path);
+        print_diff(&cnt, &modified, &old_name, &new_name, opts.context);
+        return Ok(());
+    }
+
+    if !changed {
+        return Ok(());
+    }
+    if let Some(backup) = &ctx.backup {
+        backup
+            .save(path)
+            .map_err(|err| Error::io("back up", path, err))?;
+    }
+    let modified = encode(&modified, format, path)?;
+    if let Some(journal) = &ctx.journal {
+        journal
+            .record(path, &encode(&cnt, format, path)?, &modified)
+            .map_err(|err| Error::io("journal", path, err))?;
+    }
 
-    if let Err(err) = stdin.read_to_string(&mut cnt) {
-        eprintln!("error: failed to read stdin: {}", err);
-        return;
+    atomic::write(path, &modified, opts.preserve_mtime)
+        .map_err(|err| Error::io("write to", path, err))?;
+    if opts.verbose {
+        println!("{:?} modified", path);
     }
-    print!("{}", re.replace_all(&cnt, replacement));
+    ctx.stats.modified();
+    Ok(())
 }
 
-fn main() {
-    let opts = Options::parse();
-    let re = Regex::new(opts.pattern.as_str()).unwrap();
+/// Returns whether the content of stdin was changed.
+fn process_stdin(
+    re: &Regex,
+    replacement: &Replacement,
+    address: Option<&Address>,
+    limits: &Limits,
+    opts: &Options,
+) -> Result<bool, Error> {
+    let mut cnt = Vec::new();
+    io::stdin()
+        .read_to_end(&mut cnt)
+        .map_err(|err| Error::io("read", "<stdin>", err))?;
 
-    if opts.path == "-" {
-        return process_stdin(&re, &opts.replacement);
+    let mut progress = Progress::new(address, limits);
+    let path = Path::new("<stdin>");
+    let (modified, counts) = replace_all(&cnt, re, replacement, opts, path, &mut progress);
+    if opts.diff {
+        print_diff(&cnt, &modified, "<stdin>", "<stdout>", opts.context);
+    } else {
+        let _ = io::stdout().lock().write_all(&modified);
+    }
+    Ok(counts.replacements > 0)
+}
+
+/// Compiles `pattern`, to match bytes rather than UTF-8 characters in byte
+/// mode, for the patterns not affected by the pattern options.
+fn compile(pattern: &str, walk: &WalkOptions) -> Result<Regex, Error> {
+    Ok(RegexBuilder::new(pattern).unicode(!walk.bytes).build()?)
+}
+
+fn find(opts: &FindOptions) -> Result<ExitCode, Error> {
+    let re = Pattern::new(&opts.pattern, &opts.pattern_opts, !opts.walk.bytes).compile()?;
+    let color = match opts.color {
+        Color::Auto => io::stdout().is_terminal(),
+        Color::Always => true,
+        Color::Never => false,
+    };
+    let found = AtomicBool::new(false);
+    let search = |name: &str, cnt: &[u8]| {
+        let out = if !opts.files_with_matches {
+            find::matches(name, cnt, &re, color)
+        } else if re.is_match(cnt) {
+            format!("{}\n", name)
+        } else {
+            String::new()
+        };
+        if !out.is_empty() {
+            found.store(true, Ordering::Relaxed);
+            // Write each file's matches in one go so parallel workers don't interleave.
+            let _ = io::stdout().lock().write_all(out.as_bytes());
+        }
+    };
+
+    let files = walk::files(&opts.paths, &opts.walk)?;
+    if opts.paths.iter().any(|p| p == "-") {
+        let mut cnt = Vec::new();
+        io::stdin()
+            .read_to_end(&mut cnt)
+            .map_err(|err| Error::io("read", "<stdin>", err))?;
+        search("<stdin>", &cnt);
     }
 
-    let pattern = Pattern::new(opts.glob.as_deref().unwrap_or("*")).expect("Invalid glob pattern");
-    let walker = WalkDir::new(String::from(opts.path)).into_iter();
+    let failed = AtomicBool::new(false);
+    files.par_bridge().for_each(|e| {
+        match e.and_then(|e| Ok((read_file(e.path(), &opts.walk, opts.verbose)?, e))) {
+            Ok((Ok((cnt, _)), e)) => search(&e.path().display().to_string(), &cnt),
+            Ok((Err(_), _)) => {}
+            Err(err) => {
+                eprintln!("error: {}", err);
+                failed.store(true, Ordering::Relaxed);
+            }
+        }
+    });
+    Ok(exit_status(found.into_inner(), failed.into_inner()))
+}
+
+fn undo(opts: &UndoOptions) -> Result<ExitCode, Error> {
+    let root = opts
+        .journal_dir
+        .clone()
+        .or_else(journal::default_dir)
+        .ok_or_else(|| Error::Msg("cannot locate the journal, use --journal-dir".into()))?;
+    let runs = journal::runs(&root);
+
+    if opts.list {
+        for id in &runs {
+            if let Ok((command, entries)) = journal::load(&root, id) {
+                println!("{}\t{} files\t{}", id, entries.len(), command);
+            }
+        }
+        return Ok(exit_status(!runs.is_empty(), false));
+    }
+
+    let Some(id) = opts.run.as_ref().or(runs.last()) else {
+        eprintln!("nothing to undo");
+        return Ok(ExitCode::from(EXIT_NO_MATCH));
+    };
+    // The id is joined to the journal directory, so only take the runs in it.
+    if !runs.contains(id) {
+        return Err(Error::Msg(format!(
+            "no run {:?} in the journal, see --list",
+            id
+        )));
+    }
+    let (_, entries) = journal::load(&root, id).map_err(|err| Error::io("read run", id, err))?;
+
+    let changed: Vec<_> = entries
+        .iter()
+        .filter(|e| e.state() == State::Changed)
+        .collect();
+    if !changed.is_empty() && !opts.force {
+        for e in changed {
+            eprintln!("error: {:?} was changed since run {}", e.path, id);
+        }
+        return Err(Error::Msg(format!(
+            "refusing to undo run {}, use --force to restore anyway",
+            id
+        )));
+    }
+
+    let mut failed = false;
+    for e in entries.iter().rev() {
+        if e.state() == State::Original {
+            continue;
+        }
+        if let Err(err) = e.restore() {
+            Error::io("restore", &e.path, err).report();
+            failed = true;
+        } else if opts.verbose {
+            println!("{:?} restored", e.path);
+        }
+    }
+    if failed {
+        return Ok(ExitCode::from(EXIT_ERROR));
+    }
+    journal::remove(&root, id).map_err(|err| Error::io("remove run", id, err))?;
+    Ok(ExitCode::from(EXIT_CHANGED))
+}
+
+/// Returns the exit status of a run which `changed` something or not.
+fn exit_status(changed: bool, failed: bool) -> ExitCode {
+    ExitCode::from(match (changed, failed) {
+        (_, true) => EXIT_ERROR,
+        (true, false) => EXIT_CHANGED,
+        (false, false) => EXIT_NO_MATCH,
+    })
+}
+
+fn replace_files(
+    opts: &Options,
+    walk: &WalkOptions,
+    pattern_opts: &PatternOptions,
+) -> Result<ExitCode, Error> {
+    let pattern = Pattern::new(&opts.pattern, pattern_opts, !walk.bytes);
+    let re = pattern.compile()?;
+    let text = if walk.bytes {
+        replace::unescape(&opts.replacement)
+    } else {
+        opts.replacement.clone().into_bytes()
+    };
+    let replacement = if pattern_opts.preserve_case {
+        Replacement::preserving_case(text)
+    } else {
+        Replacement::new(
+            text,
+            !pattern_opts.fixed_strings && !opts.literal_replacement,
+        )
+    };
+    for group in replacement.missing_groups(&re) {
+        eprintln!(
+            "warning: the pattern has no group {:?} referenced by the replacement, \
+             use $$ for a literal $ or --literal-replacement",
+            group
+        );
+    }
+    let address = match (&opts.lines, &opts.from) {
+        (Some((first, last)), _) => Some(Address::Lines {
+            first: *first,
+            last: *last,
+        }),
+        (None, Some(from)) => Some(Address::Patterns {
+            from: compile(from, walk)?,
+            to: opts.to.as_ref().map(|to| compile(to, walk)).transpose()?,
+        }),
+        (None, None) => None,
+    };
+    let limits = Limits {
+        nth: opts.nth.map(|n| n as usize),
+        max_count: opts.max_count,
+        total: opts.max_total.map(AtomicUsize::new),
+    };
+
+    let files = walk::files(&opts.paths, walk)?;
+    let mut stdin_changed = false;
+    if opts.paths.iter().any(|p| p == "-") {
+        if opts.interactive {
+            return Err(Error::Msg(
+                "--interactive reads the answers from stdin, it cannot edit it".into(),
+            ));
+        }
+        stdin_changed = process_stdin(&re, &replacement, address.as_ref(), &limits, opts)?;
+    }
+    if opts.paths.iter().all(|p| p == "-") {
+        return Ok(exit_status(stdin_changed, false));
+    }
+
+    let journal = if opts.no_journal || opts.to_stdout || opts.diff {
+        None
+    } else if let Some(dir) = opts.journal_dir.clone().or_else(journal::default_dir) {
+        Some(Journal::new(dir))
+    } else {
+        return Err(Error::Msg(
+            "cannot locate the journal, use --journal-dir or --no-journal".into(),
+        ));
+    };
+    let ctx = Context {
+        opts,
+        walk,
+        re,
+        replacement,
+        stream: !opts.interactive
+            && !opts.diff
+            && walk.encoding == Encoding::Utf8
+            && (opts.line_mode
+                || pattern
+                    .parse()
+                    .is_some_and(|hir| stream::line_oriented(&hir))),
+        address,
+        limits,
+        backup: Backup::new(opts.backup.clone(), opts.backup_dir.clone()),
+        journal,
+        quit: AtomicBool::new(false),
+        stats: Stats::new(opts.stats || opts.json),
+    };
+    let process = |e: Result<walkdir::DirEntry, Error>| {
+        if let Err(err) = e.and_then(|e| process_file(e, &ctx)) {
+            eprintln!("error: {}", err);
+            ctx.stats.error();
+        }
+    };
+
+    if opts.interactive {
+        files.for_each(process);
+    } else if ctx.backup.is_some() {
+        // Finish the walk first, otherwise it could pick up the backups.
+        files.collect::<Vec<_>>().into_par_iter().for_each(process);
+    } else {
+        files.par_bridge().for_each(process);
+    }
+
+    let dry_run = opts.to_stdout || opts.diff;
+    let report = if opts.json {
+        ctx.stats.json(dry_run)
+    } else if opts.stats {
+        ctx.stats.report(dry_run)
+    } else if opts.verbose {
+        format!("{}\n", ctx.stats.summary(dry_run))
+    } else {
+        String::new()
+    };
+    // Keep stdout clean when it carries the edited content.
+    if dry_run || !(opts.stats || opts.json) {
+        eprint!("{}", report);
+    } else {
+        print!("{}", report);
+    }
+    Ok(exit_status(
+        ctx.stats.changed() || stdin_changed,
+        ctx.stats.failed(),
+    ))
+}
 
-    walker
-        .filter_entry(|e| is_hidden(e) || !opts.include_hidden)
-        .filter_map(Result::ok)
-        .filter(|e| pattern.matches(e.path().to_string_lossy().as_ref()))
-        .filter(|e| opts.depth < 0 || e.depth() <= opts.depth as usize)
-        .filter(|e| !e.path().is_dir())
-        .par_bridge()
-        .for_each(|e| process_file(e, &re, &opts.replacement, opts.verbose, opts.to_stdout));
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let status = match (&cli.command, &cli.opts) {
+        (Some(Command::Find(opts)), _) => find(opts),
+        (Some(Command::Undo(opts)), _) => undo(opts),
+        (None, Some(opts)) => replace_files(opts, &cli.walk, &cli.pattern),
+        (None, None) => unreachable!("clap requires the options without a subcommand"),
+    };
+    status.unwrap_or_else(|err| err.report())
 }
@@ -0,0 +1,213 @@
+use crate::case::Identifier;
+use crate::error::Error;
+use clap::Args;
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::hir::{Hir, HirKind};
+use regex_syntax::ParserBuilder;
+
+/// Options changing how the pattern matches.
+#[derive(Args, Debug)]
+#[group(skip)]
+pub struct PatternOptions {
+    /// Match the pattern case insensitively.
+    #[arg(short, long)]
+    pub ignore_case: bool,
+    /// Match the pattern case insensitively if it has no uppercase letter.
+    #[arg(long, conflicts_with = "ignore_case")]
+    pub smart_case: bool,
+    /// Only match the pattern as a whole word.
+    #[arg(short, long)]
+    pub word: bool,
+    /// Take the pattern as a literal string, and the replacement too, without
+    /// expanding `$` references.
+    #[arg(short = 'F', long)]
+    pub fixed_strings: bool,
+    /// Take the pattern as an identifier matched in snake_case,
+    /// SCREAMING_SNAKE_CASE, camelCase and PascalCase, and write the
+    /// replacement in the style of each match.
+    #[arg(long)]
+    pub preserve_case: bool,
+    /// Make `^` and `$` in the pattern match at the start and end of lines.
+    #[arg(long)]
+    pub multiline: bool,
+    /// Make `.` in the pattern match line feeds too.
+    #[arg(long)]
+    pub dot_all: bool,
+    /// Size limit of the compiled pattern, accepting K, M and G suffixes.
+    #[arg(long, value_name = "SIZE", value_parser = crate::parse_size)]
+    pub size_limit: Option<u64>,
+    /// Size limit of the cache of the lazy DFA used by each thread, accepting
+    /// K, M and G suffixes.
+    #[arg(long, value_name = "SIZE", value_parser = crate::parse_size)]
+    pub dfa_size_limit: Option<u64>,
+}
+
+/// Returns whether the literals of `hir` have an uppercase letter, which
+/// classes like `\pL` or `\W` are not.
+fn has_uppercase(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Literal(lit) => String::from_utf8_lossy(&lit.0)
+            .chars()
+            .any(char::is_uppercase),
+        HirKind::Repetition(rep) => has_uppercase(&rep.sub),
+        HirKind::Capture(cap) => has_uppercase(&cap.sub),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => subs.iter().any(has_uppercase),
+        HirKind::Empty | HirKind::Class(_) | HirKind::Look(_) => false,
+    }
+}
+
+/// The pattern to match, as given on the command line with its options.
+pub struct Pattern<'a> {
+    source: String,
+    opts: &'a PatternOptions,
+    case_insensitive: bool,
+    unicode: bool,
+}
+
+impl<'a> Pattern<'a> {
+    /// Makes the pattern from `pattern` and `opts`, to match bytes rather
+    /// than UTF-8 characters unless `unicode`.
+    pub fn new(pattern: &str, opts: &'a PatternOptions, unicode: bool) -> Self {
+        let mut source = if opts.preserve_case {
+            let variants: Vec<String> = Identifier::new(pattern)
+                .variants()
+                .iter()
+                .map(|v| regex::escape(v))
+                .collect();
+            format!("(?:{})", variants.join("|"))
+        } else if opts.fixed_strings {
+            regex::escape(pattern)
+        } else {
+            pattern.to_string()
+        };
+        if opts.word {
+            source = format!(r"\b(?:{})\b", source);
+        }
+        let mut pattern = Pattern {
+            source,
+            opts,
+            case_insensitive: opts.ignore_case,
+            unicode,
+        };
+        if opts.smart_case {
+            // Parsed case sensitively, so that the literals are kept as given.
+            pattern.case_insensitive = pattern.parse().is_some_and(|hir| !has_uppercase(&hir));
+        }
+        pattern
+    }
+
+    pub fn compile(&self) -> Result<Regex, Error> {
+        let mut builder = RegexBuilder::new(&self.source);
+        builder
+            .unicode(self.unicode)
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.opts.multiline)
+            .dot_matches_new_line(self.opts.dot_all);
+        if let Some(limit) = self.opts.size_limit {
+            builder.size_limit(limit as usize);
+        }
+        if let Some(limit) = self.opts.dfa_size_limit {
+            builder.dfa_size_limit(limit as usize);
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Returns the syntax tree of the pattern, as compiled.
+    pub fn parse(&self) -> Option<Hir> {
+        ParserBuilder::new()
+            .unicode(self.unicode)
+            .utf8(false)
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.opts.multiline)
+            .dot_matches_new_line(self.opts.dot_all)
+            .build()
+            .parse(&self.source)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        pattern: PatternOptions,
+    }
+
+    fn opts(args: &[&str]) -> PatternOptions {
+        Cli::parse_from(std::iter::once("jet").chain(args.iter().copied())).pattern
+    }
+
+    #[test]
+    fn source() {
+        let cases: [(&[&str], &str, &str); 5] = [
+            (&[], "a.b", "a.b"),
+            (&["-F"], "a.b$", r"a\.b\$"),
+            (&["-w"], "a|b", r"\b(?:a|b)\b"),
+            (&["-w", "-F"], "a+", r"\b(?:a\+)\b"),
+            (&["--preserve-case"], "a_b", "(?:A_B|a_b|AB|aB)"),
+        ];
+        for (args, pattern, source) in cases {
+            let opts = opts(args);
+            assert_eq!(
+                Pattern::new(pattern, &opts, true).source,
+                source,
+                "{:?}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn smart_case() {
+        let opts = opts(&["--smart-case"]);
+        let cases = [
+            ("foo", true),
+            ("Foo", false),
+            (r"\pLoo", true),
+            (r"\PLoo", true),
+            (r"\p{Lu}oo", true),
+            (r"\Woo", true),
+            (r"\x7Foo", true),
+            (r"\u00e9", true),
+            (r"\x41", false),
+            ("(?i)Foo", true),
+            ("É", false),
+        ];
+        for (pattern, insensitive) in cases {
+            let pattern = Pattern::new(pattern, &opts, true);
+            assert_eq!(
+                pattern.case_insensitive, insensitive,
+                "{:?}",
+                pattern.source
+            );
+        }
+        // Taken literally, the letters of an escape count.
+        let fixed = self::opts(&["--smart-case", "-F"]);
+        assert!(Pattern::new(r"\pl", &fixed, true).case_insensitive);
+        assert!(!Pattern::new(r"\pL", &fixed, true).case_insensitive);
+    }
+
+    #[test]
+    fn compile() {
+        let re = |args: &[&str], pattern: &str| {
+            let opts = opts(args);
+            Pattern::new(pattern, &opts, true).compile().unwrap()
+        };
+        assert!(re(&["-i"], "foo").is_match(b"FOO"));
+        assert!(re(&["--smart-case"], r"\pLoo").is_match(b"FOO"));
+        assert!(!re(&["--smart-case"], "Foo").is_match(b"FOO"));
+        assert!(!re(&["-w"], "foo").is_match(b"food"));
+        assert!(re(&["-w"], "foo").is_match(b"a foo."));
+        assert!(!re(&["-F"], "a.b").is_match(b"axb"));
+        assert!(re(&["--multiline"], "^b$").is_match(b"a\nb\nc"));
+        assert!(!re(&[], "^b$").is_match(b"a\nb\nc"));
+        assert!(re(&["--dot-all"], "a.b").is_match(b"a\nb"));
+        assert!(!re(&[], "a.b").is_match(b"a\nb"));
+        let opts = opts(&["--size-limit", "1K"]);
+        assert!(Pattern::new(r"\w{100}", &opts, true).compile().is_err());
+    }
+}
@@ -0,0 +1,494 @@
+use crate::address;
+use crate::case::Identifier;
+use crate::eol::Text;
+use regex::bytes::{Captures, Match, Regex};
+use std::borrow::Cow;
+use std::ops::{AddAssign, Range};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// What a replacement did to a text.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Counts {
+    pub matches: usize,
+    pub replacements: usize,
+    /// Bytes of the replaced matches.
+    pub removed: usize,
+    /// Bytes of the replacements put in their place.
+    pub inserted: usize,
+}
+
+impl AddAssign for Counts {
+    fn add_assign(&mut self, other: Counts) {
+        self.matches += other.matches;
+        self.replacements += other.replacements;
+        self.removed += other.removed;
+        self.inserted += other.inserted;
+    }
+}
+
+/// What to do with a match.
+pub enum Action {
+    Replace,
+    Skip,
+    /// Leave this match and all the following ones alone.
+    Stop,
+}
+
+/// What the matches are replaced with.
+pub struct Replacement {
+    text: Vec<u8>,
+    /// Whether `$` references to capture groups are expanded.
+    expand: bool,
+    /// The replacement as an identifier, written in the style of each match
+    /// in case preserving mode.
+    identifier: Option<Identifier>,
+}
+
+impl Replacement {
+    pub fn new(text: Vec<u8>, expand: bool) -> Self {
+        Replacement {
+            text,
+            expand,
+            identifier: None,
+        }
+    }
+
+    /// Makes a replacement following the case of each match.
+    pub fn preserving_case(text: Vec<u8>) -> Self {
+        Replacement {
+            identifier: Some(Identifier::new(&String::from_utf8_lossy(&text))),
+            text,
+            expand: false,
+        }
+    }
+
+    /// Writes the replacement of the match `caps` to `dst`.
+    fn write(&self, caps: &Captures, dst: &mut Vec<u8>) {
+        if let Some(identifier) = &self.identifier {
+            let matched = String::from_utf8_lossy(&caps[0]);
+            dst.extend_from_slice(identifier.restyle(&matched).as_bytes());
+        } else if self.expand {
+            caps.expand(&self.text, dst);
+        } else {
+            dst.extend_from_slice(&self.text);
+        }
+    }
+
+    /// Returns the capture groups referenced by the replacement which `re`
+    /// doesn't have, read the way `Captures::expand` does.
+    pub fn missing_groups(&self, re: &Regex) -> Vec<String> {
+        let mut missing = Vec::new();
+        if !self.expand {
+            return missing;
+        }
+        let mut rest = &self.text[..];
+        while let Some(i) = rest.iter().position(|&b| b == b'$') {
+            rest = &rest[i + 1..];
+            let name = match rest.first() {
+                Some(b'$') => {
+                    rest = &rest[1..];
+                    continue;
+                }
+                Some(b'{') => match rest.iter().position(|&b| b == b'}') {
+                    Some(end) => {
+                        let name = &rest[1..end];
+                        rest = &rest[end + 1..];
+                        name
+                    }
+                    None => continue,
+                },
+                _ => {
+                    let len = rest
+                        .iter()
+                        .take_while(|&&b| b == b'_' || b.is_ascii_alphanumeric())
+                        .count();
+                    let name = &rest[..len];
+                    rest = &rest[len..];
+                    name
+                }
+            };
+            let Ok(name) = std::str::from_utf8(name) else {
+                continue;
+            };
+            let exists = match name.parse::<usize>() {
+                Ok(i) => i < re.captures_len(),
+                Err(_) => re.capture_names().any(|n| n == Some(name)),
+            };
+            if !name.is_empty() && !exists && !missing.iter().any(|m| m == name) {
+                missing.push(name.to_string());
+            }
+        }
+        missing
+    }
+}
+
+/// Limits on the number of matches replaced.
+#[derive(Debug, Default)]
+pub struct Limits {
+    /// Only the n-th match of each file is replaced.
+    pub nth: Option<usize>,
+    /// Most matches replaced in each file.
+    pub max_count: Option<usize>,
+    /// Matches left to replace in the whole run.
+    pub total: Option<AtomicUsize>,
+}
+
+/// Follows a file's replacements against the limits.
+pub struct Quota<'a> {
+    limits: &'a Limits,
+    seen: usize,
+    replaced: usize,
+}
+
+impl<'a> Quota<'a> {
+    pub fn new(limits: &'a Limits) -> Self {
+        Quota {
+            limits,
+            seen: 0,
+            replaced: 0,
+        }
+    }
+
+    /// Decides about the next match, which is left alone if `unchanged` by
+    /// its replacement but still counts towards the n-th.
+    pub fn decide(&mut self, unchanged: bool) -> Action {
+        self.seen += 1;
+        if unchanged {
+            return Action::Skip;
+        }
+        match self.limits.nth {
+            Some(nth) if self.seen < nth => return Action::Skip,
+            Some(nth) if self.seen > nth => return Action::Stop,
+            _ => {}
+        }
+        if self
+            .limits
+            .max_count
+            .is_some_and(|max| self.replaced >= max)
+        {
+            return Action::Stop;
+        }
+        if let Some(total) = &self.limits.total {
+            let take =
+                total.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+            if take.is_err() {
+                return Action::Stop;
+            }
+        }
+        self.replaced += 1;
+        Action::Replace
+    }
+}
+
+/// Replaces the matches of `re` in `text` with the expansion of `replacement`,
+/// asking `decide` about each one along with what it would be replaced with.
+/// Only the matches within `region` are replaced if given, and the ones left
+/// after a stop are still counted.
+pub fn replace<'a, F>(
+    text: &Text<'a>,
+    re: &Regex,
+    replacement: &Replacement,
+    region: Option<&[Range<usize>]>,
+    mut decide: F,
+) -> (Cow<'a, [u8]>, Counts)
+where
+    F: FnMut(&Match, &[u8]) -> Action,
+{
+    let mut counts = Counts::default();
+    let mut out = Vec::new();
+    let mut last = 0;
+    let mut dst = Vec::new();
+    let mut stopped = false;
+    let cnt = text.matched();
+
+    for caps in re.captures_iter(cnt) {
+        let m = caps.get(0).unwrap();
+        if region.is_some_and(|region| !address::contains(region, m.range())) {
+            continue;
+        }
+        counts.matches += 1;
+        if stopped {
+            continue;
+        }
+        dst.clear();
+        replacement.write(&caps, &mut dst);
+        match decide(&m, &dst) {
+            Action::Replace => {}
+            Action::Skip => continue,
+            Action::Stop => {
+                stopped = true;
+                continue;
+            }
+        }
+        if dst == m.as_bytes() {
+            continue;
+        }
+        let dst = text.with_line_endings(&dst);
+        out.extend_from_slice(text.original(last..m.start()));
+        out.extend_from_slice(&dst);
+        last = m.end();
+        counts.replacements += 1;
+        counts.removed += text.original(m.range()).len();
+        counts.inserted += dst.len();
+    }
+
+    if counts.replacements == 0 {
+        return (Cow::Borrowed(text.raw()), counts);
+    }
+    out.extend_from_slice(text.original(last..cnt.len()));
+    (Cow::Owned(out), counts)
+}
+
+/// Replaces the matches of `re` in each line of `cnt` on its own, leaving
+/// out its line ending, a CRLF as well as a LF with `crlf`. Only the lines
+/// within `region` are changed if given, `decide` is asked about the matches
+/// like by `replace`, and `changed` is given the index of each line modified.
+pub fn replace_lines<'a, D, F>(
+    cnt: &'a [u8],
+    re: &Regex,
+    replacement: &Replacement,
+    crlf: bool,
+    region: Option<&[Range<usize>]>,
+    mut decide: D,
+    mut changed: F,
+) -> (Cow<'a, [u8]>, Counts)
+where
+    D: FnMut(&Match, &[u8]) -> Action,
+    F: FnMut(usize),
+{
+    let mut counts = Counts::default();
+    let mut out = Vec::new();
+    let (mut last, mut start) = (0, 0);
+
+    for (i, line) in cnt.split_inclusive(|&b| b == b'\n').enumerate() {
+        let span = start..start + line.len();
+        start = span.end;
+        if region.is_some_and(|region| !address::contains(region, span.clone())) {
+            continue;
+        }
+        let eol = if crlf && line.ends_with(b"\r\n") {
+            2
+        } else {
+            line.ends_with(b"\n") as usize
+        };
+        let line = &line[..line.len() - eol];
+        let (new, c) = replace(&Text::new(line, false), re, replacement, None, &mut decide);
+        if c.replacements > 0 {
+            out.extend_from_slice(&cnt[last..span.start]);
+            out.extend_from_slice(&new);
+            last = span.start + line.len();
+            changed(i);
+        }
+        counts += c;
+    }
+
+    if counts.replacements == 0 {
+        return (Cow::Borrowed(cnt), counts);
+    }
+    out.extend_from_slice(&cnt[last..]);
+    (Cow::Owned(out), counts)
+}
+
+/// Turns the `\xNN` escapes of `replacement` into the bytes they stand for,
+/// and `\\` into a single backslash.
+pub fn unescape(replacement: &str) -> Vec<u8> {
+    let src = replacement.as_bytes();
+    let mut out = Vec::with_capacity(src.len());
+    let mut i = 0;
+    while i < src.len() {
+        let hex = src.get(i + 2..i + 4).and_then(|h| {
+            let h = std::str::from_utf8(h).ok()?;
+            u8::from_str_radix(h, 16).ok()
+        });
+        match (src[i], src.get(i + 1), hex) {
+            (b'\\', Some(b'x'), Some(byte)) => {
+                out.push(byte);
+                i += 4;
+            }
+            (b'\\', Some(b'\\'), _) => {
+                out.push(b'\\');
+                i += 2;
+            }
+            (b, _, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Replaces the matches of `pattern` in `cnt` within `limits`, returning
+    /// the result and the matches and replacements counted.
+    fn limited(
+        pattern: &str,
+        replacement: &str,
+        cnt: &str,
+        limits: &Limits,
+    ) -> (String, usize, usize) {
+        let re = Regex::new(pattern).unwrap();
+        let replacement = Replacement::new(replacement.as_bytes().to_vec(), true);
+        let mut quota = Quota::new(limits);
+        let (out, counts) = replace(
+            &Text::new(cnt.as_bytes(), false),
+            &re,
+            &replacement,
+            None,
+            |m, dst| quota.decide(dst == m.as_bytes()),
+        );
+        (
+            String::from_utf8(out.into_owned()).unwrap(),
+            counts.matches,
+            counts.replacements,
+        )
+    }
+
+    #[test]
+    fn quota_decisions() {
+        let limits = Limits {
+            nth: None,
+            max_count: Some(2),
+            total: None,
+        };
+        let mut quota = Quota::new(&limits);
+        let actions: Vec<_> = [false, true, false, false, false]
+            .into_iter()
+            .map(|unchanged| match quota.decide(unchanged) {
+                Action::Replace => 'r',
+                Action::Skip => 's',
+                Action::Stop => 'x',
+            })
+            .collect();
+        assert_eq!(actions, ['r', 's', 'r', 'x', 'x']);
+    }
+
+    #[test]
+    fn nth_counts_every_match() {
+        let limits = Limits {
+            nth: Some(2),
+            ..Limits::default()
+        };
+        assert_eq!(
+            limited(r"v\d", "v2", "v1 v2 v3", &limits),
+            ("v1 v2 v3".into(), 3, 0)
+        );
+        assert_eq!(
+            limited(r"v\d", "X", "v1 v2 v3", &limits),
+            ("v1 X v3".into(), 3, 1)
+        );
+    }
+
+    #[test]
+    fn limits_keep_counting_matches() {
+        let limits = Limits {
+            max_count: Some(1),
+            ..Limits::default()
+        };
+        assert_eq!(
+            limited("foo", "bar", "foo foo foo", &limits),
+            ("bar foo foo".into(), 3, 1)
+        );
+
+        let limits = Limits {
+            total: Some(AtomicUsize::new(1)),
+            ..Limits::default()
+        };
+        assert_eq!(
+            limited("foo", "bar", "foo foo", &limits),
+            ("bar foo".into(), 2, 1)
+        );
+        assert_eq!(limited("foo", "bar", "foo", &limits), ("foo".into(), 1, 0));
+    }
+
+    #[test]
+    fn unescape_bytes() {
+        assert_eq!(unescape(r"a\x00\xff\\x\xzz\"), b"a\0\xff\\x\\xzz\\");
+        assert_eq!(unescape(r"\x4A\x4a\x4"), b"JJ\\x4");
+    }
+
+    #[test]
+    fn replace_with_crlf() {
+        let re = Regex::new("(?m)a$").unwrap();
+        let replacement = Replacement::new(b"b\nc".to_vec(), true);
+        let text = Text::new(b"a\r\nxa\r\n", true);
+        let (out, counts) = replace(&text, &re, &replacement, None, |_, _| Action::Replace);
+        assert_eq!(&*out, b"b\r\nc\r\nxb\r\nc\r\n");
+        assert_eq!((counts.matches, counts.removed, counts.inserted), (2, 2, 8));
+    }
+
+    #[test]
+    fn replace_each_line() {
+        let re = Regex::new("^x|y$").unwrap();
+        let replacement = Replacement::new(b"-".to_vec(), true);
+        let mut changed = Vec::new();
+        let (out, counts) = replace_lines(
+            b"xay\r\nbb\nyx\nx",
+            &re,
+            &replacement,
+            true,
+            None,
+            |_, _| Action::Replace,
+            |i| changed.push(i),
+        );
+        assert_eq!(&*out, b"-a-\r\nbb\nyx\n-");
+        assert_eq!(counts.replacements, 3);
+        assert_eq!(changed, [0, 3]);
+
+        // Without `crlf` the `\r` is part of the line.
+        let (out, _) = replace_lines(
+            b"xy\r\n",
+            &re,
+            &replacement,
+            false,
+            None,
+            |_, _| Action::Replace,
+            |_| {},
+        );
+        assert_eq!(&*out, b"-y\r\n");
+    }
+
+    #[test]
+    fn replace_within_region() {
+        let re = Regex::new("a+").unwrap();
+        let replacement = Replacement::new(b"-".to_vec(), true);
+        let text = Text::new(b"a\naa\na\r\na", true);
+        // The second and fourth lines, as offsets in the text with LF line
+        // endings.
+        let region = [2..5, 7..8];
+        let all = |_: &Match, _: &[u8]| Action::Replace;
+        let (out, counts) = replace(&text, &re, &replacement, Some(&region), all);
+        assert_eq!(&*out, b"a\n-\na\r\n-");
+        assert_eq!(counts.matches, 2);
+
+        // Matches straddling the edge of a range are left out.
+        let re = Regex::new("a\na").unwrap();
+        let region = [0..2, 5..7];
+        let (out, counts) = replace(&text, &re, &replacement, Some(&region), all);
+        assert_eq!(&*out, text.raw());
+        assert_eq!(counts.matches, 0);
+    }
+
+    #[test]
+    fn expansion() {
+        let re = Regex::new("(?P<w>a)(b)").unwrap();
+        let expanded = Replacement::new(b"$2${w}$$".to_vec(), true);
+        let literal = Replacement::new(b"$2${w}$$".to_vec(), false);
+        let text = Text::new(b"ab", false);
+        let all = |_: &Match, _: &[u8]| Action::Replace;
+        assert_eq!(&*replace(&text, &re, &expanded, None, all).0, b"ba$");
+        assert_eq!(&*replace(&text, &re, &literal, None, all).0, b"$2${w}$$");
+    }
+
+    #[test]
+    fn missing_groups() {
+        let re = Regex::new("(?P<w>a)(b)").unwrap();
+        let missing = Replacement::new(b"$0 $1 $3 $w $x ${y} ${w} $1a $$z $ ${".to_vec(), true);
+        assert_eq!(missing.missing_groups(&re), ["3", "x", "y", "1a"]);
+        let literal = Replacement::new(b"$3".to_vec(), false);
+        assert!(literal.missing_groups(&re).is_empty());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::replace::Counts;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Why a file was left out of a run.
+#[derive(Clone, Copy, Debug)]
+pub enum Skip {
+    Unreadable,
+    Binary,
+    NonUtf8,
+}
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Skip::Unreadable => "unreadable",
+            Skip::Binary => "binary",
+            Skip::NonUtf8 => "non UTF-8",
+        })
+    }
+}
+
+/// What a run did, gathered from all the worker threads.
+pub struct Stats {
+    start: Instant,
+    scanned: AtomicUsize,
+    matched: AtomicUsize,
+    modified: AtomicUsize,
+    unreadable: AtomicUsize,
+    binary: AtomicUsize,
+    non_utf8: AtomicUsize,
+    errors: AtomicUsize,
+    /// Files with at least one match, only kept when a report is wanted.
+    files: Option<Mutex<Vec<(PathBuf, Counts)>>>,
+}
+
+fn load(n: &AtomicUsize) -> usize {
+    n.load(Ordering::Relaxed)
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_counts(out: &mut String, counts: &Counts) {
+    let _ = write!(
+        out,
+        "\"matches\":{},\"replacements\":{},\"bytes_removed\":{},\"bytes_inserted\":{}",
+        counts.matches, counts.replacements, counts.removed, counts.inserted
+    );
+}
+
+impl Stats {
+    pub fn new(per_file: bool) -> Self {
+        Stats {
+            start: Instant::now(),
+            scanned: AtomicUsize::new(0),
+            matched: AtomicUsize::new(0),
+            modified: AtomicUsize::new(0),
+            unreadable: AtomicUsize::new(0),
+            binary: AtomicUsize::new(0),
+            non_utf8: AtomicUsize::new(0),
+            errors: AtomicUsize::new(0),
+            files: per_file.then(|| Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn skip(&self, skip: Skip) {
+        let n = match skip {
+            Skip::Unreadable => &self.unreadable,
+            Skip::Binary => &self.binary,
+            Skip::NonUtf8 => &self.non_utf8,
+        };
+        n.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Accounts for a scanned file.
+    pub fn file(&self, path: &Path, counts: Counts) {
+        self.scanned.fetch_add(1, Ordering::Relaxed);
+        if counts.matches == 0 {
+            return;
+        }
+        self.matched.fetch_add(1, Ordering::Relaxed);
+        if let Some(files) = &self.files {
+            files.lock().unwrap().push((path.to_path_buf(), counts));
+        }
+    }
+
+    /// Accounts for a file modified, or that would be in a dry run.
+    pub fn modified(&self) {
+        self.modified.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Accounts for an error reported to the user.
+    pub fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns whether any file was modified, or would be in a dry run.
+    pub fn changed(&self) -> bool {
+        load(&self.modified) > 0
+    }
+
+    /// Returns whether any error occurred.
+    pub fn failed(&self) -> bool {
+        load(&self.errors) > 0
+    }
+
+    /// Returns the matched files sorted by path, and the sum of their counts.
+    fn files(&self) -> (Vec<(PathBuf, Counts)>, Counts) {
+        let mut files = match &self.files {
+            Some(files) => files.lock().unwrap().clone(),
+            None => Vec::new(),
+        };
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut total = Counts::default();
+        for (_, counts) in &files {
+            total += *counts;
+        }
+        (files, total)
+    }
+
+    /// Returns the one line summary of the files processed.
+    pub fn summary(&self, dry_run: bool) -> String {
+        format!(
+            "{} files scanned, {} matched, {} {}",
+            load(&self.scanned),
+            load(&self.matched),
+            load(&self.modified),
+            if dry_run {
+                "would be modified"
+            } else {
+                "modified"
+            }
+        )
+    }
+
+    /// Returns the human readable report of the run.
+    pub fn report(&self, dry_run: bool) -> String {
+        let (files, total) = self.files();
+        let mut out = String::new();
+        for (path, c) in &files {
+            let _ = writeln!(
+                out,
+                "{}: {} matches, {} replacements, -{}/+{} bytes",
+                path.display(),
+                c.matches,
+                c.replacements,
+                c.removed,
+                c.inserted
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}\n{} matches, {} replacements, -{}/+{} bytes\n\
+             {} files skipped: {} unreadable, {} binary, {} non UTF-8\n\
+             {} errors\n\
+             elapsed: {:.3}s",
+            self.summary(dry_run),
+            total.matches,
+            total.replacements,
+            total.removed,
+            total.inserted,
+            load(&self.unreadable) + load(&self.binary) + load(&self.non_utf8),
+            load(&self.unreadable),
+            load(&self.binary),
+            load(&self.non_utf8),
+            load(&self.errors),
+            self.start.elapsed().as_secs_f64()
+        );
+        out
+    }
+
+    /// Returns the report of the run as a JSON object.
+    pub fn json(&self, dry_run: bool) -> String {
+        let (files, total) = self.files();
+        let mut out = String::from("{\"files\":[");
+        for (i, (path, counts)) in files.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{{\"path\":{},", json_string(&path.to_string_lossy()));
+            json_counts(&mut out, counts);
+            out.push('}');
+        }
+        let _ = write!(
+            out,
+            "],\"total\":{{\"scanned\":{},\"matched\":{},\"modified\":{},",
+            load(&self.scanned),
+            load(&self.matched),
+            load(&self.modified)
+        );
+        json_counts(&mut out, &total);
+        let _ = writeln!(
+            out,
+            "}},\"skipped\":{{\"unreadable\":{},\"binary\":{},\"non_utf8\":{}}},\
+             \"errors\":{},\"dry_run\":{},\"elapsed\":{:.6}}}",
+            load(&self.unreadable),
+            load(&self.binary),
+            load(&self.non_utf8),
+            load(&self.errors),
+            dry_run,
+            self.start.elapsed().as_secs_f64()
+        );
+        out
+    }
+}
@@ -0,0 +1,110 @@
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+use std::io::{self, BufRead, BufReader, Read};
+
+/// Size of the blocks read at once, extended to the end of their last line.
+const BLOCK_SIZE: u64 = 1 << 20;
+
+/// Returns whether the matches of `hir` never span several lines nor depend
+/// on where the whole text starts or ends.
+fn within_lines(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Empty => true,
+        HirKind::Literal(lit) => !lit.0.contains(&b'\n'),
+        HirKind::Class(Class::Unicode(class)) => !class
+            .ranges()
+            .iter()
+            .any(|r| r.start() <= '\n' && '\n' <= r.end()),
+        HirKind::Class(Class::Bytes(class)) => !class
+            .ranges()
+            .iter()
+            .any(|r| r.start() <= b'\n' && b'\n' <= r.end()),
+        HirKind::Look(look) => !matches!(look, Look::Start | Look::End),
+        HirKind::Repetition(rep) => within_lines(&rep.sub),
+        HirKind::Capture(cap) => within_lines(&cap.sub),
+        HirKind::Concat(subs) | HirKind::Alternation(subs) => subs.iter().all(within_lines),
+    }
+}
+
+/// Returns whether the pattern parsed into `hir` finds the same matches in
+/// a text as in the blocks of whole lines it is split into: it can't match a
+/// line feed, the start or end of the text, or an empty string.
+pub fn line_oriented(hir: &Hir) -> bool {
+    hir.properties().minimum_len().is_some_and(|len| len > 0) && within_lines(hir)
+}
+
+/// Reads whole lines in blocks of about `BLOCK_SIZE` bytes.
+pub struct Blocks<R> {
+    reader: BufReader<R>,
+}
+
+impl<R: Read> Blocks<R> {
+    pub fn new(inner: R) -> Self {
+        Blocks {
+            reader: BufReader::new(inner),
+        }
+    }
+
+    /// Reads the next block into `buf`, returning false at the end.
+    pub fn read(&mut self, buf: &mut Vec<u8>) -> io::Result<bool> {
+        buf.clear();
+        (&mut self.reader).take(BLOCK_SIZE).read_to_end(buf)?;
+        if buf.last().is_some_and(|&b| b != b'\n') {
+            self.reader.read_until(b'\n', buf)?;
+        }
+        Ok(!buf.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oriented(pattern: &str) -> bool {
+        line_oriented(&regex_syntax::parse(pattern).unwrap())
+    }
+
+    #[test]
+    fn line_oriented_patterns() {
+        for pattern in ["foo", r"(?m)^foo$", r"\bfoo", r"[a-z]+\d", "a|b", "(x)+"] {
+            assert!(oriented(pattern), "{:?}", pattern);
+        }
+        let rejected = [
+            r"\A", r"\z", "^foo", "foo$", "(?s).", "[^x]", r"\s", r"a\nb", "a|", "x*",
+        ];
+        for pattern in rejected {
+            assert!(!oriented(pattern), "{:?}", pattern);
+        }
+    }
+
+    /// Returns the blocks `cnt` is read in.
+    fn blocks(cnt: &[u8]) -> Vec<Vec<u8>> {
+        let mut blocks = Blocks::new(cnt);
+        let mut buf = Vec::new();
+        let mut out = Vec::new();
+        while blocks.read(&mut buf).unwrap() {
+            out.push(buf.clone());
+        }
+        out
+    }
+
+    #[test]
+    fn blocks_of_whole_lines() {
+        assert!(blocks(b"").is_empty());
+        assert_eq!(blocks(b"a\nb"), [b"a\nb"]);
+
+        let size = BLOCK_SIZE as usize;
+        let line = [&[b'x'; 1000][..], b"\n"].concat();
+        let cnt = line.repeat(size / line.len() * 3 / 2);
+        let read = blocks(&cnt);
+        assert_eq!(read.len(), 2);
+        assert!(read.iter().all(|b| b.len() % line.len() == 0));
+        assert!(read[0].len() >= size);
+        assert_eq!(read.concat(), cnt);
+
+        // A line longer than a block is read whole, even without a line feed.
+        let long = [vec![b'y'; size + 10], b"\nz".to_vec()].concat();
+        assert_eq!(blocks(&long), [&long[..size + 11], b"z"]);
+        let long = vec![b'y'; 2 * size + 10];
+        assert_eq!(blocks(&long), [&long[..]]);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::encoding::Encoding;
+use crate::error::Error;
+use crate::ignore::Ignore;
+use clap::Args;
+use glob::{MatchOptions, Pattern};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use walkdir::{DirEntry, WalkDir};
+
+/// Options selecting the files to go through.
+#[derive(Args, Debug)]
+#[group(skip)]
+pub struct WalkOptions {
+    /// Only process the files matching the glob, or not matching it when
+    /// prefixed with `!`. Can be repeated. Unlike in earlier versions the
+    /// glob is matched against the file name, see `--match-path`.
+    #[arg(short = 'g', long, visible_alias = "glob", value_name = "GLOB")]
+    pub include: Vec<String>,
+    /// Skip the files and directories matching the glob, unless prefixed with
+    /// `!`, which brings back what an earlier glob excluded. Can be repeated.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+    /// Match the globs against the path relative to the search root rather
+    /// than the file name.
+    #[arg(long)]
+    pub match_path: bool,
+    /// Max depth in a directory tree, the paths given being at depth 0.
+    #[arg(short = 'l', long = "level", value_name = "DEPTH")]
+    pub max_depth: Option<usize>,
+    /// Skip the files less deep in a directory tree.
+    #[arg(long, value_name = "DEPTH", default_value_t = 0)]
+    pub min_depth: usize,
+    /// Include hidden files and directories (starting with a dot).
+    #[arg(short = 'a', long = "all")]
+    pub include_hidden: bool,
+    /// Process binary files as raw bytes instead of skipping them.
+    #[arg(long)]
+    pub binary: bool,
+    /// Process text which isn't UTF-8 too, matching bytes rather than UTF-8
+    /// characters: `.` matches any byte, `\xNN` the byte NN, and `\xNN` in
+    /// the replacement inserts it.
+    #[arg(long)]
+    pub bytes: bool,
+    /// Encoding of the files without a byte order mark, the others being
+    /// decoded as it tells. The files are written back in their encoding.
+    #[arg(long, value_enum, default_value_t = Encoding::Utf8)]
+    pub encoding: Encoding,
+    /// Don't skip the files matched by .gitignore, .ignore, .jetignore and
+    /// the global git excludes.
+    #[arg(long)]
+    pub no_ignore: bool,
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry
+        .file_name()
+        .to_str()
+        .map(|s| s.starts_with("."))
+        .unwrap_or(false)
+}
+
+/// The include and exclude globs, checked in order with the last match
+/// deciding whether an entry is kept.
+struct Globs {
+    globs: Vec<(Pattern, bool)>,
+    /// Whether files must match an include glob to be kept.
+    include_only: bool,
+    match_path: bool,
+}
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+impl Globs {
+    fn new(opts: &WalkOptions) -> Result<Self, Error> {
+        let mut globs = Vec::new();
+        let includes = opts.include.iter().map(|g| (g, true));
+        let excludes = opts.exclude.iter().map(|g| (g, false));
+        for (glob, include) in includes.chain(excludes) {
+            let (negated, pat) = match glob.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, glob.as_str()),
+            };
+            let pat = pat.strip_prefix('/').unwrap_or(pat);
+            let pattern = Pattern::new(pat).map_err(|err| Error::Glob(glob.clone(), err))?;
+            globs.push((pattern, include != negated));
+        }
+        Ok(Globs {
+            include_only: opts.include.iter().any(|g| !g.starts_with('!')),
+            globs,
+            match_path: opts.match_path,
+        })
+    }
+
+    /// Returns whether `entry`, found walking from `root`, is kept. Directories
+    /// are only pruned by exclusions, and the roots never are.
+    fn keep(&self, root: &Path, entry: &DirEntry) -> bool {
+        let is_dir = entry.file_type().is_dir();
+        if is_dir && entry.depth() == 0 {
+            return true;
+        }
+        let path = match entry.path().strip_prefix(root) {
+            Ok(rel) if self.match_path && entry.depth() > 0 => rel,
+            _ => Path::new(entry.file_name()),
+        };
+        self.matched(&path.to_string_lossy().replace('\\', "/"), is_dir)
+    }
+
+    /// Returns whether `path`, the file name or the relative path with
+    /// `--match-path`, is kept.
+    fn matched(&self, path: &str, is_dir: bool) -> bool {
+        match self
+            .globs
+            .iter()
+            .rev()
+            .find(|(p, _)| p.matches_with(path, MATCH_OPTIONS))
+        {
+            Some((_, include)) => *include,
+            None => is_dir || !self.include_only,
+        }
+    }
+}
+
+/// Returns the files in the trees rooted at `paths` selected by `opts`, along
+/// with the errors met while walking them. Files reached from several roots
+/// are only returned once, `-` paths are ignored.
+pub fn files<'a>(
+    paths: &'a [String],
+    opts: &'a WalkOptions,
+) -> Result<impl Iterator<Item = Result<DirEntry, Error>> + 'a, Error> {
+    if let Some(max) = opts.max_depth.filter(|max| *max < opts.min_depth) {
+        return Err(Error::Msg(format!(
+            "--min-depth {} is greater than --level {}",
+            opts.min_depth, max
+        )));
+    }
+    let globs = Arc::new(Globs::new(opts)?);
+    let mut seen = HashSet::new();
+
+    Ok(paths
+        .iter()
+        .filter(|path| *path != "-")
+        .flat_map(move |path| {
+            let root = Path::new(path);
+            let globs = Arc::clone(&globs);
+            let mut ignore = (!opts.no_ignore).then(|| Ignore::new(root));
+            // The min depth is checked afterwards, as the walker doesn't give
+            // the entries above it to `filter_entry`.
+            WalkDir::new(path)
+                .max_depth(opts.max_depth.unwrap_or(usize::MAX))
+                .into_iter()
+                .filter_entry(move |e| {
+                    // The roots are walked even if hidden, as they were asked for.
+                    (opts.include_hidden || e.depth() == 0 || !is_hidden(e))
+                        && globs.keep(root, e)
+                        && ignore.as_mut().is_none_or(|i| i.keep(e))
+                })
+        })
+        .filter(move |e| match e {
+            Ok(e) => e.depth() >= opts.min_depth && !e.path().is_dir(),
+            Err(_) => true,
+        })
+        .filter(move |e| match e {
+            Ok(e) => seen.insert(fs::canonicalize(e.path()).unwrap_or_else(|_| e.path().into())),
+            Err(_) => true,
+        })
+        .map(|e| e.map_err(Error::from)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        walk: WalkOptions,
+    }
+
+    fn globs(args: &[&str]) -> Globs {
+        let cli = Cli::parse_from(std::iter::once("jet").chain(args.iter().copied()));
+        Globs::new(&cli.walk).unwrap()
+    }
+
+    #[test]
+    fn globs_keep() {
+        let cases: [(&[&str], &str, bool, bool); 14] = [
+            (&[], "a.rs", false, true),
+            (&["-g", "*.rs"], "a.rs", false, true),
+            (&["-g", "*.rs"], "a.md", false, false),
+            // Directories are only pruned by exclusions.
+            (&["-g", "*.rs"], "src", true, true),
+            (&["-g", "!*.md"], "a.rs", false, true),
+            (&["-g", "!*.md"], "a.md", false, false),
+            (&["--exclude", "target"], "target", true, false),
+            (&["--exclude", "*.md"], "a.rs", false, true),
+            // The last matching glob wins, the excludes coming after the
+            // includes.
+            (
+                &["-g", "*.rs", "--exclude", "main.rs"],
+                "main.rs",
+                false,
+                false,
+            ),
+            (
+                &["--exclude", "*.rs", "--exclude", "!main.rs"],
+                "main.rs",
+                false,
+                true,
+            ),
+            (
+                &["--exclude", "*.rs", "--exclude", "!main.rs"],
+                "lib.rs",
+                false,
+                false,
+            ),
+            (
+                &["-g", "!gen_*", "-g", "gen_keep.rs"],
+                "gen_keep.rs",
+                false,
+                true,
+            ),
+            // Without `--match-path`, a glob with a `/` can't match a name.
+            (&["-g", "src/*.rs"], "main.rs", false, false),
+            (
+                &["-g", "src/*.rs", "--match-path"],
+                "src/main.rs",
+                false,
+                true,
+            ),
+        ];
+        for (args, path, is_dir, kept) in cases {
+            assert_eq!(
+                globs(args).matched(path, is_dir),
+                kept,
+                "{:?} {}",
+                args,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn match_path_separators() {
+        let globs = globs(&["--match-path", "-g", "/src/*.rs"]);
+        assert!(globs.matched("src/main.rs", false));
+        // `*` doesn't cross directories, `**` does.
+        assert!(!globs.matched("src/bin/main.rs", false));
+        let globs = self::globs(&["--match-path", "-g", "src/**/*.rs"]);
+        assert!(globs.matched("src/bin/main.rs", false));
+    }
+}